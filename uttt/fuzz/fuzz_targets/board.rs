@@ -0,0 +1,38 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use uttt::rules::{DrawnSquares, FreeMove, TieBreak};
+use uttt::{Board, Player, Rules};
+
+// The first byte picks the rules and every other byte is a space
+// to move in, where 255 undoes the last move instead. Moves may be
+// out of range or illegal, which must be refused without changing
+// the board
+fuzz_target!(|data: &[u8]| {
+    let (rule_bits, ops) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let rules = Rules {
+        tie_break: if rule_bits & 1 != 0 { TieBreak::MostSquares } else { TieBreak::Draw },
+        drawn_squares: if rule_bits & 2 != 0 { DrawnSquares::Both } else { DrawnSquares::Nobody },
+        free_move: if rule_bits & 4 != 0 { FreeMove::AnyBoard } else { FreeMove::OpenBoards },
+        dead_boards: rule_bits & 8 != 0,
+    };
+    let mut board = Board::new(2, rules).unwrap();
+    for op in ops {
+        if *op == 255 {
+            board.undo_move();
+        } else {
+            let space = *op as usize;
+            let legal = board.get_moves().contains(&space);
+            let before = board.clone();
+            assert_eq!(board.check_move(space).is_ok(), legal);
+            assert_eq!(board.make_move(space).is_ok(), legal);
+            if !legal {
+                assert!(board == before);
+            }
+        }
+        assert_eq!(board.validate(), Ok(()));
+        assert_eq!(board.get_moves().is_empty(), board.winner != Player::NEITHER);
+    }
+});
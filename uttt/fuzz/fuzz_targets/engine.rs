@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use uttt::engine::{mcts_server, search_server};
+use uttt::eval::Weights;
+
+// Feeds arbitrary commands, setoption included, to the servers
+// uttt-engine runs. The first byte picks the AI, the rest are
+// the commands
+fuzz_target!(|data: &[u8]| {
+    let (mode, input) = match data.split_first() {
+        Some((mode, rest)) => match std::str::from_utf8(rest) {
+            Ok(input) => (mode, input),
+            Err(_) => return,
+        },
+        None => return,
+    };
+    let mut server = if mode % 2 == 0 {
+        search_server(Weights::diagonal2(), None)
+    } else {
+        mcts_server()
+    };
+    let mut output = Vec::new();
+    server.run(&mut Cursor::new(input.as_bytes()), &mut output).unwrap();
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use uttt::book::Book;
+use uttt::eval::Weights;
+use uttt::GameRecord;
+
+// Anything the text formats accept must be written back in a
+// form they read the same way
+fuzz_target!(|text: &str| {
+    if let Ok(record) = text.parse::<GameRecord>() {
+        assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record));
+    }
+    if let Ok(book) = text.parse::<Book>() {
+        assert_eq!(book.to_string().parse::<Book>(), Ok(book));
+    }
+    if let Ok(weights) = text.parse::<Weights>() {
+        assert_eq!(weights.to_string().parse::<Weights>(), Ok(weights));
+    }
+});
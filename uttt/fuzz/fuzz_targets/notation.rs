@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use uttt::notation::{game_name, parse_game, parse_space, space_name};
+use uttt::Rules;
+
+fuzz_target!(|text: &str| {
+    if let Ok(space) = parse_space(text) {
+        assert!(space < 81);
+        assert_eq!(parse_space(&space_name(space)), Ok(space));
+    }
+    if let Ok(moves) = parse_game(text, Rules::default()) {
+        assert_eq!(parse_game(&game_name(&moves), Rules::default()), Ok(moves));
+    }
+});
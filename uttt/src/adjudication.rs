@@ -0,0 +1,136 @@
+use crate::board::{Board, Player, Square};
+use crate::record::Termination;
+use crate::rules::TieBreak;
+
+// When the game runner may end a game before it is over
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Adjudication {
+    // A game is won once both AIs have reported scores of at least
+    // win_score for the same side for win_moves moves each in a row.
+    // Scores are compared as reported, so this is only meaningful
+    // between AIs whose scores are on similar scales
+    pub win_score: Option<i32>,
+    pub win_moves: usize,
+    // A game is drawn once neither player can complete a line of
+    // small boards (see Board::is_dead)
+    pub draw: bool,
+}
+
+// Applies an Adjudication to the moves of one game
+pub struct Adjudicator {
+    rules: Adjudication,
+    // The side every score in the current run of decisive
+    // scores favors
+    leader: Player,
+    // Length in moves of the current run of decisive scores
+    streak: usize,
+}
+
+impl Adjudicator {
+    pub fn new(_rules: Adjudication) -> Adjudicator {
+        Adjudicator {
+            rules: _rules,
+            leader: Player::NEITHER,
+            streak: 0,
+        }
+    }
+
+    // Call after every move with the board after the move, the
+    // player who made it and the score that player's AI reported
+    // for it. Returns the result of the game and why, if the game
+    // should be adjudicated
+    pub fn after_move(&mut self, board: &Board, mover: Player,
+                      score: Option<i32>) -> Option<(Player, Termination)> {
+        if board.winner != Player::NEITHER {
+            return None;
+        }
+        // Under other tie breaks a dead board is still decided
+        // by the small boards won
+        if self.rules.draw && board.rules().tie_break == TieBreak::Draw
+           && board.is_dead(Square { top_left: 0, level: 2 }) == Ok(true) {
+            return Some((Player::DEAD, Termination::AdjudicatedDraw));
+        }
+        let threshold = self.rules.win_score?;
+        let favored = match score {
+            Some(s) if s >= threshold => mover,
+            Some(s) if s <= -threshold => if mover == Player::X { Player::O } else { Player::X },
+            _ => Player::NEITHER,
+        };
+        if favored == Player::NEITHER || favored != self.leader {
+            self.streak = 0;
+        }
+        self.leader = favored;
+        if favored != Player::NEITHER {
+            self.streak += 1;
+        }
+        // Both AIs have to agree, so the run is counted in plies
+        if self.streak > 0 && self.streak >= 2 * self.rules.win_moves.max(1) {
+            return Some((favored, Termination::AdjudicatedWin));
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+     #[test]
+     fn test_win_adjudication() {
+         let board = Board::new(2, Rules::default()).unwrap();
+         let mut a = Adjudicator::new(Adjudication {
+             win_score: Some(500),
+             win_moves: 2,
+             draw: false,
+         });
+         // X's AI likes its position and O's AI agrees, but
+         // one disagreement restarts the count
+         assert_eq!(a.after_move(&board, Player::X, Some(600)), None);
+         assert_eq!(a.after_move(&board, Player::O, Some(-700)), None);
+         assert_eq!(a.after_move(&board, Player::X, Some(100)), None);
+         assert_eq!(a.after_move(&board, Player::O, Some(-900)), None);
+         assert_eq!(a.after_move(&board, Player::X, Some(900)), None);
+         assert_eq!(a.after_move(&board, Player::O, Some(-900)), None);
+         assert_eq!(a.after_move(&board, Player::X, None), None);
+         assert_eq!(a.after_move(&board, Player::O, Some(-500)), None);
+         assert_eq!(a.after_move(&board, Player::X, Some(500)), None);
+         assert_eq!(a.after_move(&board, Player::O, Some(-500)), None);
+         assert_eq!(a.after_move(&board, Player::X, Some(500)),
+                    Some((Player::X, Termination::AdjudicatedWin)));
+         let mut off = Adjudicator::new(Adjudication::default());
+         assert_eq!(off.after_move(&board, Player::X, Some(100000)), None);
+     }
+
+     #[test]
+     fn test_draw_adjudication() {
+         // Once a draw is forced, no random continuation
+         // of the game can be won
+         let mut rng = StdRng::seed_from_u64(0);
+         let mut forced = 0;
+         for _i in 0..200 {
+             let mut board = Board::new(2, Rules::default()).unwrap();
+             let mut a = Adjudicator::new(Adjudication { win_score: None, win_moves: 0,
+                                                         draw: true });
+             let mut adjudicated = false;
+             while board.winner == Player::NEITHER {
+                 let moves = board.get_moves();
+                 let mover = board.get_to_move();
+                 assert!(board.make_move(moves[rng.gen_range(0, moves.len())]).is_ok());
+                 if !adjudicated && a.after_move(&board, mover, None).is_some() {
+                     adjudicated = true;
+                     forced += 1;
+                 }
+             }
+             if adjudicated {
+                 assert_eq!(board.winner, Player::DEAD);
+             }
+         }
+         assert!(forced > 0);
+     }
+}
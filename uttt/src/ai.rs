@@ -1,9 +1,62 @@
+use crate::error::Error;
+use crate::rules::Rules;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
 pub trait AI {
     // returns the move the AI wants to make
     // given that the last move was last_move
-    // last_move should be -1 if this is the first move of the game
-    // get_move returns -1 if the ai wants to resign
-    fn get_move(&mut self, last_move : i64) -> i64;
+    // last_move should be -1 if this is the first move of the game,
+    // or the first move after the opening given to set_opening
+    // get_move returns -1 if the ai wants to resign, and an error
+    // if it couldn't choose a move, like an engine that crashed
+    fn get_move(&mut self, last_move : i64) -> Result<i64, Error>;
 
     fn cleanup(&mut self);
-}
\ No newline at end of file
+
+    // Seeds every random choice the AI makes, so that a game
+    // between seeded AIs can be replayed exactly. AIs that
+    // make no random choices can ignore it
+    fn set_seed(&mut self, _seed: u64) {}
+
+    // Plays the game under rules instead of the standard ones.
+    // Must be called before set_opening and the first get_move.
+    // Returns false if the AI can't play under them
+    fn set_rules(&mut self, rules: Rules) -> bool {
+        return rules == Rules::default();
+    }
+
+    // Starts the game from the position after moves instead of
+    // the empty board. Must be called before the first get_move.
+    // Returns false if the AI can't start from a position
+    fn set_opening(&mut self, _moves: &[usize]) -> bool {
+        return false;
+    }
+
+    // Enables or disables thinking on the opponent's time after
+    // returning a move. Returns false if the AI can't ponder
+    fn set_ponder(&mut self, _enabled: bool) -> bool {
+        return false;
+    }
+
+    // Makes get_move return the best move it has found so far
+    // soon after stop is set. Whoever sets stop clears it again
+    // before the next get_move. Returns false if the AI can't be
+    // stopped, and always answers in its own time
+    fn set_stop(&mut self, _stop: Arc<AtomicBool>) -> bool {
+        return false;
+    }
+
+    // The reply the AI expects to the move it last returned,
+    // if it has one in mind
+    fn ponder_move(&self) -> Option<i64> {
+        return None;
+    }
+
+    // The score the AI gave the move it last returned, from its
+    // own side, larger is better. None if it has no score, for
+    // example for a book move
+    fn last_score(&self) -> Option<i32> {
+        return None;
+    }
+}
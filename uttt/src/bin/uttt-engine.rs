@@ -0,0 +1,58 @@
+// Runs one of the built-in AIs as a uttti engine on stdin and
+// stdout, so that it can play against external bots and be used
+// by any driver that speaks the protocol (see PROTOCOL.md)
+#![allow(clippy::needless_return)]
+
+use std::io::{self, BufReader};
+use structopt::StructOpt;
+use uttt::book::Book;
+use uttt::engine::{self, EngineServer};
+use uttt::eval::Weights;
+
+#[derive(StructOpt)]
+#[structopt(name = "uttt-engine")]
+struct Cli {
+    /// The AI to run: search (alpha-beta with a linear evaluator) or mcts
+    #[structopt(long = "ai", default_value = "search")]
+    ai: String,
+    /// Weights file for the search AI instead of diagonal2
+    #[structopt(long = "weights")]
+    weights: Option<String>,
+    /// Opening book for the search AI instead of always opening in the center
+    #[structopt(long = "book")]
+    book: Option<String>,
+}
+
+// The server for the AI chosen on the command line
+fn server(cli: &Cli) -> Result<EngineServer, String> {
+    return match cli.ai.as_str() {
+        "search" => {
+            let weights = match &cli.weights {
+                Some(path) => Weights::load(path)?,
+                None => Weights::diagonal2(),
+            };
+            let book = match &cli.book {
+                Some(path) => Some(Book::load(path)?),
+                None => None,
+            };
+            Ok(engine::search_server(weights, book))
+        },
+        "mcts" => Ok(engine::mcts_server()),
+        other => Err(format!("unknown AI {}, expected search or mcts", other)),
+    };
+}
+
+fn main() {
+    let cli = Cli::from_args();
+    let result = server(&cli).and_then(|mut s| {
+        // Unlike a StdinLock, the reader can be read from the
+        // server's command thread
+        let stdout = io::stdout();
+        return s.run(&mut BufReader::new(io::stdin()), &mut stdout.lock())
+            .map_err(|e| e.to_string());
+    });
+    if let Err(why) = result {
+        eprintln!("{}", why);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::board::{IllegalReason, Player, Square};
+use crate::rules::{DrawnSquares, FreeMove, Rules, TieBreak};
+use rand::{Rng, RngCore};
+
+// A compact representation of the two-level board used by the
+// search based AIs
+//
+// Spaces are numbered the same way as in Board:
+// 00 01 02  09 10 11  18 19 20
+// 03 04 05  12 13 14  21 22 23
+// 06 07 08  15 16 17  24 25 26
+//
+// 27 28 29  36 37 38  45 46 47
+// 30 31 32  39 40 41  48 49 50
+// 33 34 35  42 43 44  51 52 53
+//
+// 54 55 56  63 64 65  72 73 74
+// 57 58 59  66 67 68  75 76 77
+// 60 61 62  69 70 71  78 79 80
+// Bit n of an occupancy is set iff the player occupies space n,
+// and bit 81 + i is set iff the player has captured small board i,
+// where small boards are numbered
+// 0 1 2
+// 3 4 5
+// 6 7 8
+// A small board that is neither captured nor full is open, unless
+// it is dead under Rules::dead_boards
+#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+pub struct BitBoard {
+    pub x_occupancy: u128,
+    pub o_occupancy: u128,
+    // 1 if X will make the next move, -1 if O will
+    pub to_move: i8,
+    // The small board the next move must be made in,
+    // or ANY_SQUARE if the next move may be made in any
+    // small board that is still open
+    next_square: u8,
+    // 1 if X has won, -1 if O has won, -2 if the game
+    // is drawn and 0 if the game is still ongoing
+    winner: i8,
+    rules: Rules,
+}
+
+const ANY_SQUARE: u8 = 9;
+const SQUARE_MASK: u128 = 0x1ff;
+const SPACES_MASK: u128 = (1 << 81) - 1;
+
+// The eight lines of a 3x3 board as masks of its nine subsquares
+static LINES: [u128; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+impl Default for BitBoard {
+    fn default() -> Self {
+        BitBoard::new()
+    }
+}
+
+impl BitBoard {
+    // Creates an empty board with X to move
+    pub fn new() -> BitBoard {
+        return BitBoard::with_rules(Rules::default());
+    }
+
+    // Creates an empty board with X to move for a game played
+    // under _rules
+    pub fn with_rules(_rules: Rules) -> BitBoard {
+        BitBoard {
+            x_occupancy: 0,
+            o_occupancy: 0,
+            to_move: 1,
+            next_square: ANY_SQUARE,
+            winner: 0,
+            rules: _rules,
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        return self.rules;
+    }
+
+    // Returns 1 if X has won, -1 if O has won, -2 if the game
+    // is drawn and 0 if the game is still ongoing
+    pub fn get_winner(&self) -> i8 {
+        return self.winner;
+    }
+
+    // Does the 3x3 board described by the nine bits
+    // of square contain a complete line?
+    pub fn has_line(square: u128) -> bool {
+        for line in LINES.iter() {
+            if square & line == *line {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // Is small board i neither captured, full nor dead?
+    fn square_open(&self, i: usize) -> bool {
+        let captured = (self.x_occupancy | self.o_occupancy) & (1 << (81 + i));
+        let full = ((self.x_occupancy | self.o_occupancy) >> (9 * i)) & SQUARE_MASK
+                   == SQUARE_MASK;
+        return captured == 0 && !full && !(self.rules.dead_boards && self.square_dead(i));
+    }
+
+    // Does every line of small board i hold spaces of both players?
+    fn square_dead(&self, i: usize) -> bool {
+        let x = (self.x_occupancy >> (9 * i)) & SQUARE_MASK;
+        let o = (self.o_occupancy >> (9 * i)) & SQUARE_MASK;
+        return LINES.iter().all(|l| x & l != 0 && o & l != 0);
+    }
+
+    // The small boards that are closed without being captured,
+    // which count for both players under DrawnSquares::Both
+    fn drawn_squares(&self) -> u128 {
+        if self.rules.drawn_squares == DrawnSquares::Nobody {
+            return 0;
+        }
+        let captured = ((self.x_occupancy | self.o_occupancy) >> 81) & SQUARE_MASK;
+        return (0..9).filter(|i| !self.square_open(*i))
+                     .fold(0, |drawn, i| drawn | 1 << i) & !captured;
+    }
+
+    // Can the player with mine still complete a line of small
+    // boards against the player with theirs? A small board can
+    // still be captured by a player if it is open and has a line
+    // without any of the other player's spaces
+    fn line_possible(&self, mine: u128, theirs: u128) -> bool {
+        let mut winnable = (mine >> 81) & SQUARE_MASK | self.drawn_squares();
+        for i in 0..9 {
+            let blocked = (theirs >> (9 * i)) & SQUARE_MASK;
+            if self.square_open(i) && LINES.iter().any(|l| blocked & l == 0) {
+                winnable |= 1 << i;
+            }
+        }
+        return BitBoard::has_line(winnable);
+    }
+
+    // Can neither player complete a line of small boards any more?
+    // True for every drawn game, and the positions it is true for
+    // can only end in a draw. Under TieBreak::MostSquares, where
+    // such a game is decided by counting small boards, it is only
+    // true once the game is drawn
+    pub fn draw_forced(&self) -> bool {
+        if self.winner != 0 || self.rules.tie_break == TieBreak::MostSquares {
+            return self.winner == -2;
+        }
+        return !self.line_possible(self.x_occupancy, self.o_occupancy)
+               && !self.line_possible(self.o_occupancy, self.x_occupancy);
+    }
+
+    // The winner after a move by mover that changed the small
+    // boards, in the form of get_winner
+    fn top_result(&self, mover: i8) -> i8 {
+        let (mine, theirs) = if mover == 1 {
+            (self.x_occupancy, self.o_occupancy)
+        } else {
+            (self.o_occupancy, self.x_occupancy)
+        };
+        let drawn = self.drawn_squares();
+        if BitBoard::has_line((mine >> 81) & SQUARE_MASK | drawn) {
+            return mover;
+        }
+        if drawn != 0 && BitBoard::has_line((theirs >> 81) & SQUARE_MASK | drawn) {
+            return -mover;
+        }
+        let full = (0..9).all(|i| !self.square_open(i));
+        if !full {
+            let dead = self.rules.dead_boards && self.rules.tie_break == TieBreak::Draw
+                       && !self.line_possible(mine, theirs)
+                       && !self.line_possible(theirs, mine);
+            return if dead { -2 } else { 0 };
+        }
+        if self.rules.tie_break == TieBreak::MostSquares {
+            let x = (self.x_occupancy >> 81).count_ones();
+            let o = (self.o_occupancy >> 81).count_ones();
+            if x != o {
+                return if x > o { 1 } else { -1 };
+            }
+        }
+        return -2;
+    }
+
+    // Returns a mask of the current legal moves, which is
+    // zero iff the game is over
+    pub fn get_moves(&self) -> u128 {
+        if self.winner != 0 {
+            return 0;
+        }
+        let empty = !(self.x_occupancy | self.o_occupancy) & SPACES_MASK;
+        if self.next_square != ANY_SQUARE {
+            return empty & (SQUARE_MASK << (9 * self.next_square as usize));
+        }
+        if self.rules.free_move == FreeMove::AnyBoard {
+            return empty;
+        }
+        let mut moves = 0;
+        for i in 0..9 {
+            if self.square_open(i) {
+                moves |= empty & (SQUARE_MASK << (9 * i));
+            }
+        }
+        return moves;
+    }
+
+    // Returns why the next move can't be made on space, if it
+    // can't, in the terms of Board::check_move
+    pub fn check_move(&self, space: usize) -> Result<(), IllegalReason> {
+        if space >= 81 {
+            return Err(IllegalReason::OutOfRange);
+        }
+        if self.winner != 0 {
+            return Err(IllegalReason::GameOver);
+        }
+        if self.x_occupancy & (1 << space) != 0 {
+            return Err(IllegalReason::Occupied(Player::X));
+        }
+        if self.o_occupancy & (1 << space) != 0 {
+            return Err(IllegalReason::Occupied(Player::O));
+        }
+        let square = space / 9;
+        if self.next_square != ANY_SQUARE && square != self.next_square as usize {
+            let next_legal = Square { top_left: 9 * self.next_square as usize, level: 1 };
+            return Err(IllegalReason::WrongSquare { next_legal });
+        }
+        if self.get_moves() & (1 << space) == 0 {
+            let owner = if self.x_occupancy & (1 << (81 + square)) != 0 {
+                Player::X
+            } else if self.o_occupancy & (1 << (81 + square)) != 0 {
+                Player::O
+            } else {
+                Player::DEAD
+            };
+            let square = Square { top_left: 9 * square, level: 1 };
+            return Err(IllegalReason::ClosedSquare { square, owner });
+        }
+        return Ok(());
+    }
+
+    // Make the move with its single bit set in m for the player
+    // to move. m must be one of the moves returned by get_moves
+    pub fn make_move(&mut self, m: u128) {
+        let space = m.trailing_zeros() as usize;
+        let square = space / 9;
+        let mover = self.to_move;
+        // A move in a closed small board (FreeMove::AnyBoard)
+        // changes nothing above it
+        let was_open = self.square_open(square);
+        let mine = if mover == 1 {
+            &mut self.x_occupancy
+        } else {
+            &mut self.o_occupancy
+        };
+        *mine |= m;
+        if was_open && BitBoard::has_line((*mine >> (9 * square)) & SQUARE_MASK) {
+            *mine |= 1 << (81 + square);
+        }
+        self.to_move = -self.to_move;
+        // Under dead boards a move that closes nothing can still
+        // kill the big board
+        if was_open && (!self.square_open(square) || self.rules.dead_boards) {
+            self.winner = self.top_result(mover);
+            if self.winner != 0 {
+                return;
+            }
+        }
+        self.next_square = (space % 9) as u8;
+        if !self.square_open(space % 9) {
+            self.next_square = ANY_SQUARE;
+        }
+    }
+
+    // The number of spaces that are not occupied by either player
+    pub fn empty_spaces(&self) -> u32 {
+        return 81 - ((self.x_occupancy | self.o_occupancy) & SPACES_MASK).count_ones();
+    }
+
+    // A 64 bit hash of the position, used as the key of
+    // transposition table entries
+    pub fn hash(&self) -> u64 {
+        let mut h = (self.next_square as u64) << 1 | (self.to_move == 1) as u64;
+        for word in [self.x_occupancy as u64, (self.x_occupancy >> 64) as u64,
+                     self.o_occupancy as u64, (self.o_occupancy >> 64) as u64].iter() {
+            // splitmix64 finalizer
+            h = (h ^ word).wrapping_add(0x9e3779b97f4a7c15);
+            h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+            h ^= h >> 31;
+        }
+        return h;
+    }
+
+    // Calls f with each move in moves, both as a single bit mask
+    // and as a space index, until f returns false
+    pub fn iterate_moves<F>(moves: u128, f: &mut F)
+        where F: FnMut(u128, i64) -> bool {
+        let mut remaining = moves;
+        while remaining != 0 {
+            let space = remaining.trailing_zeros();
+            let m = 1 << space;
+            remaining &= !m;
+            if !f(m, space as i64) {
+                return;
+            }
+        }
+    }
+
+    // Returns one of the moves in moves chosen uniformly at random
+    pub fn random_move(moves: u128, rng: &mut dyn RngCore) -> u128 {
+        let mut n = rng.gen_range(0, moves.count_ones());
+        let mut remaining = moves;
+        while n > 0 {
+            remaining &= remaining - 1;
+            n -= 1;
+        }
+        return remaining & remaining.wrapping_neg();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Player, Square};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+     // Play moves on both a Board and a BitBoard, checking that
+     // they agree on the legal moves after every move
+     fn play_both(moves: &[usize]) -> (Board, BitBoard) {
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         let mut bb = BitBoard::new();
+         for i in moves {
+             let mut expected = 0;
+             for m in b.get_moves() {
+                 expected |= 1 << m;
+             }
+             assert_eq!(bb.get_moves(), expected);
+             assert!(b.make_move(*i).is_ok());
+             bb.make_move(1 << *i);
+         }
+         return (b, bb);
+     }
+
+     #[test]
+     fn test_basic_moves() {
+         let (b, bb) = play_both(&[20, 22, 38, 21, 29, 23, 50, 49, 41, 46, 14, 52, 68]);
+         assert_eq!(bb.get_winner(), 0);
+         assert_eq!(bb.get_moves() & (1 << 48), 0);
+         assert_eq!(bb.to_move, if b.get_to_move() == Player::X { 1 } else { -1 });
+     }
+
+     #[test]
+     fn test_basic_victory() {
+         let (_, bb) = play_both(&[0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14,
+                                   47, 22, 38, 21, 29, 23]);
+         assert_eq!(bb.get_winner(), -1);
+         assert_eq!(bb.get_moves(), 0);
+     }
+
+     #[test]
+     fn test_full_square_ascend() {
+         play_both(&[0, 1, 10, 9, 5, 45, 7, 70, 71, 80, 72, 4, 36, 8,
+                     73, 11, 18, 2, 20, 21, 27, 3, 33, 54, 6,
+                     61, 63, 13]);
+     }
+
+     #[test]
+     fn test_draw() {
+         let (_, bb) = play_both(&[0, 1, 9, 4, 36, 7, 70, 71, 79, 67, 43, 63, 20, 21,
+                                   31, 40, 37, 13, 38, 23, 49, 22, 10, 14, 52, 55, 11,
+                                   50, 46, 30, 29, 27, 32, 33, 58, 78, 59, 72, 57, 73, 74,
+                                   76, 77, 80]);
+         assert_eq!(bb.get_winner(), -2);
+     }
+
+     // Every combination of the rule variants
+     fn all_rules() -> Vec<Rules> {
+         let mut all = Vec::new();
+         for i in 0..16 {
+             all.push(Rules {
+                 tie_break: if i & 1 == 0 { TieBreak::Draw } else { TieBreak::MostSquares },
+                 drawn_squares: if i & 2 == 0 { DrawnSquares::Nobody } else { DrawnSquares::Both },
+                 free_move: if i & 4 == 0 { FreeMove::OpenBoards } else { FreeMove::AnyBoard },
+                 dead_boards: i & 8 != 0,
+             });
+         }
+         return all;
+     }
+
+     #[test]
+     fn test_random_games() {
+         let mut rng = StdRng::seed_from_u64(0);
+         for rules in all_rules() {
+             for _i in 0..100 {
+                 random_game(rules, &mut rng);
+             }
+         }
+     }
+
+     // Plays random moves on both a Board and a BitBoard for a
+     // game under rules, checking that they agree after every move
+     fn random_game(rules: Rules, rng: &mut StdRng) {
+         let top = Square { top_left: 0, level: 2 };
+         let mut b = Board::new(2, rules).unwrap();
+         let mut bb = BitBoard::with_rules(rules);
+         while bb.get_winner() == 0 {
+             let mut expected = 0;
+             for m in b.get_moves() {
+                 expected |= 1 << m;
+             }
+             assert_eq!(bb.get_moves(), expected, "{:?}", rules);
+             for space in 0..82 {
+                 assert_eq!(bb.check_move(space), b.check_move(space), "{:?}", rules);
+             }
+             let dead = b.is_dead(top).unwrap()
+                        && (rules.tie_break == TieBreak::Draw || b.winner == Player::DEAD);
+             assert_eq!(bb.draw_forced(), dead, "{:?}", rules);
+             let m = BitBoard::random_move(bb.get_moves(), rng);
+             assert!(b.make_move(m.trailing_zeros() as usize).is_ok());
+             bb.make_move(m);
+         }
+         let expected = match b.winner {
+             Player::X => 1,
+             Player::O => -1,
+             Player::DEAD => -2,
+             Player::NEITHER => 0,
+         };
+         assert_eq!(bb.get_winner(), expected);
+         assert_eq!(bb.draw_forced(), expected == -2);
+         assert_eq!(bb.check_move(0), Err(IllegalReason::GameOver));
+     }
+}
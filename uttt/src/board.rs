@@ -1,10 +1,15 @@
-use std::collections::HashMap;
 //use std::thread;
 use std::hash::{Hash};
+use crate::error::Error;
+use crate::notation::square_name;
+use crate::rules::{DrawnSquares, FreeMove, Rules, TieBreak};
+use std::fmt;
 
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Player {
     X,
     O,
@@ -24,12 +29,16 @@ pub enum Player {
 //              1. A single space
 //              2. A collection of 9 squares
 // Drawn - A square is drawn if all of its subsquares are occupied
+// Dead - A square is dead if neither player can complete a line
+//        of its subsquares any more. Every drawn square is dead,
+//        but most dead squares still have empty subsquares
 
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
 #[derive(Debug)]
 #[derive(Hash)]
 #[derive(Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Square {
     // the integer corresponding to the 
     // space in the top left corner of this square
@@ -40,13 +49,50 @@ pub struct Square {
     pub level: usize,
 }
 
+// Why a move can't be made, see Board::check_move
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum IllegalReason {
+    // The space isn't on the board
+    OutOfRange,
+    GameOver,
+    // The space is taken by this player
+    Occupied(Player),
+    // The move is outside next_legal, the small board
+    // the last move sent the player to
+    WrongSquare { next_legal: Square },
+    // The small board is closed, because owner won it or it is
+    // drawn or dead (owner is DEAD). Only reported for free moves,
+    // since other moves into it are in the WrongSquare
+    ClosedSquare { square: Square, owner: Player },
+}
+
+impl fmt::Display for IllegalReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            IllegalReason::OutOfRange => write!(f, "the space is not on the board"),
+            IllegalReason::GameOver => write!(f, "the game is over"),
+            IllegalReason::Occupied(player) => write!(f, "the space is taken by {:?}", player),
+            IllegalReason::WrongSquare { next_legal } =>
+                write!(f, "the move has to be made in {}", square_name(*next_legal)),
+            IllegalReason::ClosedSquare { square, owner: Player::DEAD } =>
+                write!(f, "{} is closed", square_name(*square)),
+            IllegalReason::ClosedSquare { square, owner } =>
+                write!(f, "{} was won by {:?}", square_name(*square), owner),
+        };
+    }
+}
+
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
 #[derive(Debug)]
 #[derive(Hash)]
 #[derive(Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Turn {
     pub space: usize,
+    // The small board the move closed, or 81 if none
     pub capture: usize,
     pub bounds: Square,
 }
@@ -72,6 +118,9 @@ pub struct Turn {
 // top left corner at 00 and its bottom right corner at 08
 #[derive(Debug)]
 #[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "Position", try_from = "Game"))]
 pub struct Board {
     // the index of the top level in the board e.g.
     // max_level = 1 is a standard 3x3 tic-tac-toe board
@@ -81,8 +130,6 @@ pub struct Board {
     to_move: Player,
     // Spaces and their occupation status
     spaces: Vec<Player>,
-    // Maps higher level squares to their occupation status
-    occupied: HashMap<Square, Player>,
     // Tuple describing the upper left corner and level
     // of the next legal move space
     pub next_legal: Square,
@@ -96,7 +143,62 @@ pub struct Board {
     pub move_history: Vec<Turn>,
     // The size in spaces of a square at level index <= max_level
     level_sizes: Vec<usize>,
+    rules: Rules,
+}
+
+// How a Board is written with serde: its rules and moves, followed
+// by the state they lead to for readers that don't know the rules
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct Position {
+    rules: Rules,
+    moves: Vec<usize>,
+    to_move: Player,
+    winner: Player,
+    next_legal: Square,
+    // The owners of the 81 spaces and of the 9 small boards
+    spaces: Vec<Player>,
+    squares: Vec<Player>,
+}
+
+// How a Board is read with serde. It is rebuilt by replaying the
+// moves, so anything else written with it is ignored
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Game {
+    #[serde(default)]
+    rules: Rules,
+    moves: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Board> for Position {
+    fn from(board: Board) -> Position {
+        return Position {
+            rules: board.rules,
+            moves: board.move_history.iter().map(|t| t.space).collect(),
+            to_move: board.to_move,
+            winner: board.winner,
+            next_legal: board.next_legal,
+            spaces: board.spaces[..81].to_vec(),
+            squares: board.spaces[81..90].to_vec(),
+        };
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<Game> for Board {
+    type Error = Error;
+
+    fn try_from(game: Game) -> Result<Board, Error> {
+        let mut board = Board::new(2, game.rules)?;
+        for m in game.moves {
+            board.make_move(m)?;
+        }
+        return Ok(board);
+    }
 }
+
 // Win table for all 3x3 boards
 // (Geng, 2020)
 static WIN_TABLE: [u64; 8] = [
@@ -114,17 +216,16 @@ impl Board {
     pub fn get_to_move(&self) -> Player {
         return self.to_move;
     }
-    // Creates a new board with max level max_level_
-    // where 1 is a standard 3x3 tic-tac-toe board,
-    // 2 is a 9x9 board, etc.
-    pub fn new(max_level_: usize) -> Board {
-        if max_level_ < 1 {
-            panic!("max_level_ must be >= 1");
+    // Creates a new board with max level max_level_, played under
+    // rules. Only 2, the 9x9 board, is supported by get and set
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(max_level_: usize, rules: Rules) -> Result<Board, Error> {
+        if max_level_ != 2 {
+            return Err(Error::OutOfRange { what: "board level", value: max_level_ as i64 });
         }
         let size_ = (3 as usize).pow(max_level_ as u32);
         let mut result = Board {
                 to_move: Player::X, // X goes first
-                occupied: HashMap::new(),
                 spaces: Vec::with_capacity(81),
                 // first move can be anywhere
                 next_legal: Square { top_left: 0, level: max_level_},
@@ -132,6 +233,7 @@ impl Board {
                 winner: Player::NEITHER,
                 move_history: Vec::with_capacity(81),
                 level_sizes: Vec::new(),
+                rules,
                 //capture_history: Vec::new() 
             };
         // TODO: it might be cleaner to initialize all squares (including
@@ -146,7 +248,7 @@ impl Board {
         for i in 0..=result.max_level+2 {
             result.level_sizes.push((3 as usize).pow(2*i as u32));
         }
-        return result;
+        return Ok(result);
     }
 
    // 00 01 02  09 10 11  18 19 20
@@ -160,6 +262,8 @@ impl Board {
    // 54 55 56  63 64 65  72 73 74
    // 57 58 59  66 67 68  75 76 77
    // 60 61 62  69 70 71  78 79 80
+    #[allow(clippy::unnecessary_cast)]
+    #[allow(clippy::println_empty_string)]
     pub fn pretty_print(&self) {
         // TODO: generalize this to n-levels
         // print rows in order
@@ -185,17 +289,101 @@ impl Board {
         }
     }
 
-    pub fn get(&self, sqr: Square) -> Player {
+    pub fn rules(&self) -> Rules {
+        return self.rules;
+    }
+
+    // Could player still complete a line in sqr if every empty
+    // space in it were theirs? Ignores whose turn it is and where
+    // the next moves have to be made
+    pub fn can_win(&self, sqr: Square, player: Player) -> Result<bool, Error> {
+        self.check_square(sqr)?;
+        return Ok(self.could_win(sqr, player));
+    }
+
+    fn could_win(&self, sqr: Square, player: Player) -> bool {
+        let owner = self.at(sqr);
+        if self.counts_for(owner, player) {
+            return true;
+        }
+        if owner != Player::NEITHER {
+            return false;
+        }
+        if sqr.level == 0 {
+            return true;
+        }
+        let mut open = 0;
+        for i in 0..9 {
+            if self.could_win(self.descend(&sqr, i), player) {
+                open |= 1 << i;
+            }
+        }
+        return WIN_TABLE[open / 64] & (1 << (open % 64)) != 0;
+    }
+
+    // Does a square owned by owner count as player's when
+    // looking for lines?
+    fn counts_for(&self, owner: Player, player: Player) -> bool {
+        return owner == player
+               || (owner == Player::DEAD && self.rules.drawn_squares == DrawnSquares::Both);
+    }
+
+    // Can a move be made in space, ignoring where the next
+    // move has to be made? Spaces in closed squares can only
+    // be played under FreeMove::AnyBoard, and only as a free move
+    fn playable(&self, space: usize) -> bool {
+        if self.spaces[space] != Player::NEITHER {
+            return false;
+        }
+        let sqr = self.ascend(&Square { level: 0, top_left: space }).0;
+        return self.at(sqr) == Player::NEITHER
+               || (self.rules.free_move == FreeMove::AnyBoard
+                   && self.next_legal.level == self.max_level);
+    }
+
+    // Is sqr drawn, or still open with neither player able to
+    // win it? A dead top level square means the game can only
+    // end in a draw
+    pub fn is_dead(&self, sqr: Square) -> Result<bool, Error> {
+        self.check_square(sqr)?;
+        return Ok(self.dead(sqr));
+    }
+
+    fn dead(&self, sqr: Square) -> bool {
+        return match self.at(sqr) {
+            Player::DEAD => true,
+            Player::NEITHER => !self.could_win(sqr, Player::X)
+                               && !self.could_win(sqr, Player::O),
+            _ => false,
+        };
+    }
+
+    // Who occupies sqr
+    pub fn get(&self, sqr: Square) -> Result<Player, Error> {
+        self.check_square(sqr)?;
+        return Ok(self.at(sqr));
+    }
+
+    // Is sqr a square of this board?
+    fn check_square(&self, sqr: Square) -> Result<(), Error> {
+        if sqr.level > self.max_level {
+            return Err(Error::OutOfRange { what: "level", value: sqr.level as i64 });
+        }
+        if sqr.top_left >= self.level_sizes[self.max_level]
+           || sqr.top_left % self.level_sizes[sqr.level] != 0 {
+            return Err(Error::OutOfRange { what: "top left space", value: sqr.top_left as i64 });
+        }
+        return Ok(());
+    }
+
+    fn at(&self, sqr: Square) -> Player {
         if sqr.level == 0 {
             return self.spaces[sqr.top_left];
         }
         if sqr.level == 1 {
             return self.spaces[81 + sqr.top_left / 9];
         }
-        if sqr.level == 2 {
-            return self.spaces[90];
-        }
-        panic!("Call to get with sqr > max_level: {}", sqr.level);
+        return self.spaces[90];
     }
 
     fn set(&mut self, sqr: Square, player: Player) {
@@ -254,13 +442,15 @@ impl Board {
         }
     }*/
 
-    // Returns a vector of the current legal moves
+    // Returns a vector of the current legal moves, which is
+    // empty iff the game is over
     pub fn get_moves(&self) -> Vec<usize> {
         let mut vec = Vec::with_capacity(81);
+        if self.winner != Player::NEITHER {
+            return vec;
+        }
         for i in self.next_legal.top_left..=self.bottom_right(self.next_legal) {
-            if self.spaces[i] == Player::NEITHER 
-               && self.get(self.ascend(&Square{level: 0, 
-                   top_left: i}).0) == Player::NEITHER {
+            if self.playable(i) {
                 vec.push(i);
             }
         }
@@ -290,10 +480,9 @@ impl Board {
         // We know that this will not result in a next_legal
         // larger than the entire board, because we have already
         // determined that the board is not drawn or won
-        while self.get(self.next_legal) != Player::NEITHER  {
-            if self.next_legal.level >= 2 {
-                println!("Wrong: {}", self.next_legal.level);
-            }
+        while self.at(self.next_legal) != Player::NEITHER  {
+            assert!(self.next_legal.level < self.max_level,
+                    "no open square to move in, but the game is not over");
             let (temp, _) = self.ascend(&self.next_legal);
             self.next_legal = temp;
         }
@@ -308,21 +497,38 @@ impl Board {
         }
     }
 
-    // make the next move on space space
-    // returns true iff the move is legal
-    // does not affect board state if the move is illegal
-    pub fn make_move(&mut self, space: usize) -> bool {
-        let move_sqr = Square {top_left: space, level: 0};
-        // Make sure this square is available
-        if self.spaces[space] != Player::NEITHER
-            || self.get(self.ascend(&move_sqr).0) != Player::NEITHER
-            || self.winner != Player::NEITHER {
-            return false;
+    // Returns why the next move can't be made on space,
+    // if it can't
+    pub fn check_move(&self, space: usize) -> Result<(), IllegalReason> {
+        if space >= self.level_sizes[self.max_level] {
+            return Err(IllegalReason::OutOfRange);
+        }
+        if self.winner != Player::NEITHER {
+            return Err(IllegalReason::GameOver);
+        }
+        if self.spaces[space] != Player::NEITHER {
+            return Err(IllegalReason::Occupied(self.spaces[space]));
         }
         // Check if the move is in the legal bounds
         if !self.in_bounds(space) {
-            return false;
+            return Err(IllegalReason::WrongSquare { next_legal: self.next_legal });
+        }
+        // Make sure this square is available
+        if !self.playable(space) {
+            let (square, _) = self.ascend(&Square { level: 0, top_left: space });
+            return Err(IllegalReason::ClosedSquare { square, owner: self.at(square) });
+        }
+        return Ok(());
+    }
+
+    // make the next move on space space
+    // returns why not if the move is illegal, in which
+    // case the board state is not affected
+    pub fn make_move(&mut self, space: usize) -> Result<(), Error> {
+        if let Err(reason) = self.check_move(space) {
+            return Err(Error::illegal_move(space, reason));
         }
+        let move_sqr = Square {top_left: space, level: 0};
         // Write this move to the board
         self.spaces[space] = self.to_move;
         // Put the move into the move history
@@ -330,7 +536,7 @@ impl Board {
         let mut turn = Turn {
             bounds: self.next_legal,
             capture: 81,
-            space: space,
+            space,
         };
         
         // Update occupied
@@ -338,7 +544,21 @@ impl Board {
         let check_sqr = &mut _check_sqr;
         // Check levels for captures
         while check_sqr.level <= self.max_level {
-            let victorious_player = self.check_victory(&check_sqr);
+            if self.at(*check_sqr) != Player::NEITHER {
+                // A move in a square that was already won
+                // (FreeMove::AnyBoard) changes nothing above it
+                break;
+            }
+            let mut victorious_player = self.check_victory(check_sqr);
+            if victorious_player == Player::NEITHER && self.rules.dead_boards
+               && (check_sqr.level < self.max_level || self.rules.tie_break == TieBreak::Draw)
+               && self.dead(*check_sqr) {
+                victorious_player = Player::DEAD;
+            }
+            if victorious_player == Player::DEAD && check_sqr.level == self.max_level
+               && self.rules.tie_break == TieBreak::MostSquares {
+                victorious_player = self.most_squares();
+            }
             if victorious_player != Player::NEITHER {
                 // This player or DEAD now occupies this square
                 //self.occupied.insert(*check_sqr, victorious_player);
@@ -352,9 +572,11 @@ impl Board {
                 if check_sqr.level == self.max_level {
                     self.winner = victorious_player;
                 }
-            } else {
+            } else if !self.rules.dead_boards {
                 // If no capture or draw happened at this level,
-                // then none can happen at any higher levels
+                // then none can happen at any higher levels.
+                // A move that blocks a line can kill the squares
+                // above it without capturing anything, though
                 break;
             }
             let (_check_sqr, _) = self.ascend(check_sqr);
@@ -365,12 +587,17 @@ impl Board {
         }
         self.move_history.push(turn);
         self.next_player();
-        return true;
+        #[cfg(feature = "debug-invariants")]
+        if let Err(why) = self.validate() {
+            panic!("invalid board after move {}: {}", space, why);
+        }
+        return Ok(());
     }
 
     // Undo the most recent move unless no moves have been made
     // in which case does nothing
     // Returns false iff no moves have been made
+    #[allow(clippy::len_zero)]
     pub fn undo_move(&mut self) -> bool {
         if self.move_history.len() == 0 {
             return false;
@@ -386,9 +613,109 @@ impl Board {
         }
         self.next_legal = t.bounds;
         self.next_player();
+        #[cfg(feature = "debug-invariants")]
+        if let Err(why) = self.validate() {
+            panic!("invalid board after undoing move {}: {}", t.space, why);
+        }
         return true;
     }
 
+    // Checks that the board is one that can be reached by playing
+    // the moves in move_history under its rules, returning what is
+    // wrong with it if not. Runs after every move and undo when the
+    // debug-invariants feature is enabled
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_level != 2 || self.spaces.len() != 91 {
+            return Err(format!("{} levels and {} spaces", self.max_level, self.spaces.len()));
+        }
+        // Moves alternate starting with X
+        let count = |player| self.spaces[..81].iter().filter(|p| **p == player).count();
+        let (x, o) = (count(Player::X), count(Player::O));
+        if x != o && x != o + 1 {
+            return Err(format!("X has {} spaces and O has {}", x, o));
+        }
+        let expected_to_move = if x == o { Player::X } else { Player::O };
+        if self.to_move != expected_to_move {
+            return Err(format!("{:?} to move after {} moves", self.to_move, x + o));
+        }
+        if self.move_history.len() != x + o {
+            return Err(format!("{} moves in the history but {} spaces occupied",
+                               self.move_history.len(), x + o));
+        }
+        for (n, t) in self.move_history.iter().enumerate() {
+            let mover = if n % 2 == 0 { Player::X } else { Player::O };
+            if self.spaces[t.space] != mover {
+                return Err(format!("move {} at {} is not {:?}'s", n, t.space, mover));
+            }
+            if t.capture != 81 && self.at(Square { top_left: t.capture, level: 1 })
+                                  == Player::NEITHER {
+                return Err(format!("move {} closed square {}, which is open",
+                                   n, t.capture));
+            }
+        }
+        if self.spaces[..81].contains(&Player::DEAD) {
+            return Err("a space is DEAD".to_string());
+        }
+        // Every square's status follows from its subsquares
+        let top = Square { top_left: 0, level: 2 };
+        for i in 0..9 {
+            self.validate_square(self.descend(&top, i))?;
+        }
+        self.validate_square(top)?;
+        if self.at(top) != self.winner {
+            return Err(format!("the board is {:?} but the winner is {:?}",
+                               self.at(top), self.winner));
+        }
+        // The next move goes where the last one sent it,
+        // or anywhere if that square is closed
+        if self.winner == Player::NEITHER {
+            let expected = match self.move_history.last() {
+                Some(t) if self.at(self.descend(&top, t.space % 9)) == Player::NEITHER =>
+                    self.descend(&top, t.space % 9),
+                _ => top,
+            };
+            if self.next_legal != expected {
+                return Err(format!("next move in {:?} instead of {:?}",
+                                   self.next_legal, expected));
+            }
+        }
+        return Ok(());
+    }
+
+    // Checks the status of one square above the spaces against
+    // its subsquares
+    fn validate_square(&self, sqr: Square) -> Result<(), String> {
+        let status = self.at(sqr);
+        let full = (0..9).all(|i| self.at(self.descend(&sqr, i)) != Player::NEITHER);
+        let x_line = self.has_line(&sqr, Player::X);
+        let o_line = self.has_line(&sqr, Player::O);
+        let top = sqr.level == self.max_level;
+        let dies = self.rules.dead_boards
+                   && (!top || self.rules.tie_break == TieBreak::Draw);
+        let ok = match status {
+            // Under FreeMove::AnyBoard the other player can complete
+            // a line in a square after it was won
+            Player::X => x_line || (top && full && !o_line && self.most_squares() == Player::X),
+            Player::O => o_line || (top && full && !x_line && self.most_squares() == Player::O),
+            Player::DEAD => !x_line && !o_line
+                            && ((full && (!top || self.rules.tie_break == TieBreak::Draw
+                                          || self.most_squares() == Player::DEAD))
+                                || (dies && self.is_dead_below(sqr))),
+            Player::NEITHER => !(x_line || o_line || full || (dies && self.dead(sqr))),
+        };
+        if !ok {
+            return Err(format!("{:?} is {:?}", sqr, status));
+        }
+        return Ok(());
+    }
+
+    // Would sqr be dead if it were still open?
+    fn is_dead_below(&self, sqr: Square) -> bool {
+        let mut open = self.clone();
+        open.set(sqr, Player::NEITHER);
+        return open.dead(sqr);
+    }
+
    // Return one of the nine sub-squares that make up sqr
    // where i is one of
    // 0 1 2
@@ -438,6 +765,31 @@ impl Board {
        (Square {top_left: tl, level: sqr.level + 1}, i)
    }
 
+    // Do the subsquares of sqr that count as player's
+    // contain a line?
+    fn has_line(&self, sqr: &Square, player: Player) -> bool {
+        let mut block = 0;
+        for i in 0..9 {
+            if self.counts_for(self.at(self.descend(sqr, i)), player) {
+                block |= 1 << i;
+            }
+        }
+        return WIN_TABLE[block / 64] & (1 << (block % 64)) != 0;
+    }
+
+    // The winner of a full board under TieBreak::MostSquares
+    fn most_squares(&self) -> Player {
+        let top = Square { top_left: 0, level: self.max_level };
+        let count = |player| (0..9).filter(|i| self.at(self.descend(&top, *i)) == player).count();
+        let (x, o) = (count(Player::X), count(Player::O));
+        if x > o {
+            return Player::X;
+        } else if o > x {
+            return Player::O;
+        }
+        return Player::DEAD;
+    }
+
     // Determine if the square with space at its top left corner at level
     // where 0 is the lowest level (i.e. individual squares) has been 
     // won by a player
@@ -446,19 +798,19 @@ impl Board {
     // subsquares are occupied)
     // TODO: only check for the last player that moved
     pub fn check_victory(&self, sqr: &Square) -> Player {
-        let mut block_x = 0;
-        for i in 0..9 {
-            if self.get(self.descend(sqr, i)) == self.to_move {
-                block_x |= 1 << i;
-            }
-        }
-        if WIN_TABLE[block_x as usize / 64] & (1 << (block_x % 64)) != 0 {
+        if self.has_line(sqr, self.to_move) {
             return self.to_move;
         }
+        // A drawn square that counts for both players can
+        // complete a line for the player who didn't draw it
+        let opponent = if self.to_move == Player::X { Player::O } else { Player::X };
+        if self.rules.drawn_squares == DrawnSquares::Both && self.has_line(sqr, opponent) {
+            return opponent;
+        }
         // Check for draw
         let mut draw = true;
         for i in 0..9 {
-            match self.get(self.descend(sqr, i)) {
+            match self.at(self.descend(sqr, i)) {
                 Player::NEITHER => {draw = false; break; },
                 _ => continue, 
             }
@@ -476,6 +828,8 @@ impl Board {
     // Returns the winner if so, returns NEITHER if no player has won
     // and returns DEAD if the square is drawn (i.e all of its
     // subsquares are occupied)
+    #[allow(clippy::collapsible_if)]
+    #[allow(clippy::needless_range_loop)]
     pub fn check_victory_old(&self, sqr: &Square) -> Player {
         let mut this_board: Vec<Player> = Vec::with_capacity(9); 
         // Put the owners of the 9 subsquares
@@ -484,7 +838,7 @@ impl Board {
         this_board.resize_with(9, || {
             let move_sqr = self.descend(sqr, counter);
             counter += 1;
-            self.get(move_sqr)
+            self.at(move_sqr)
         });
         
         // Check the horizontals
@@ -543,26 +897,97 @@ impl Board {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
+    use std::time::Instant;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
 
      #[test]
      fn test_basic_moves_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![20, 22, 38, 21, 29, 23, 50, 49, 41, 46, 14, 52];
          for i in moves {
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
-         assert!(b.make_move(68));
-         assert!(!b.make_move(48));
+         assert!(b.make_move(68).is_ok());
+         assert!(b.make_move(48).is_err());
+     }
+
+     #[test]
+     fn test_out_of_range_moves_2lv() {
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         assert!(b.make_move(40).is_ok());
+         let before = b.clone();
+         assert_eq!(b.make_move(81), Err(Error::OutOfRange { what: "space", value: 81 }));
+         assert!(matches!(b.make_move(usize::MAX), Err(Error::OutOfRange { .. })));
+         assert_eq!(b.check_move(usize::MAX), Err(IllegalReason::OutOfRange));
+         // Neither changed the board
+         assert_eq!(b, before);
+         assert!(b.make_move(36).is_ok());
+     }
+
+     #[test]
+     fn test_errors_2lv() {
+         assert!(Board::new(0, Rules::default()).is_err());
+         assert!(Board::new(3, Rules::default()).is_err());
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         assert_eq!(b.make_move(81), Err(Error::OutOfRange { what: "space", value: 81 }));
+         assert!(b.get(Square { top_left: 0, level: 3 }).is_err());
+         assert!(b.get(Square { top_left: 4, level: 1 }).is_err());
+         assert!(b.is_dead(Square { top_left: 81, level: 0 }).is_err());
+         assert!(b.make_move(40).is_ok());
+         assert!(matches!(b.make_move(40), Err(Error::IllegalMove { space: 40, .. })));
+         assert!(matches!(b.make_move(0), Err(Error::IllegalMove { space: 0, .. })));
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         for i in [0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23] {
+             assert!(b.make_move(i).is_ok());
+         }
+         assert_eq!(b.make_move(63), Err(Error::GameOver));
+     }
+
+     #[test]
+     fn test_check_move_2lv() {
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         assert_eq!(b.check_move(81), Err(IllegalReason::OutOfRange));
+         assert_eq!(b.check_move(40), Ok(()));
+         assert!(b.make_move(40).is_ok());
+         assert_eq!(b.check_move(40), Err(IllegalReason::Occupied(Player::X)));
+         let center = Square { top_left: 36, level: 1 };
+         assert_eq!(b.check_move(0), Err(IllegalReason::WrongSquare { next_legal: center }));
+         assert_eq!(b.check_move(0).unwrap_err().to_string(),
+                    "the move has to be made in d4-f6");
+         assert_eq!(b.make_move(0), Err(Error::IllegalMove {
+             space: 0,
+             reason: IllegalReason::WrongSquare { next_legal: center },
+         }));
+         assert_eq!(b.make_move(0).unwrap_err().to_string(),
+                    "illegal move 0: the move has to be made in d4-f6");
+         // O captures small board 0 and X then sends O there,
+         // so O has a free move outside it
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         for i in [0, 3, 27, 4, 36, 5, 45] {
+             assert!(b.make_move(i).is_ok());
+         }
+         let closed = Square { top_left: 0, level: 1 };
+         assert_eq!(b.check_move(1), Err(IllegalReason::ClosedSquare { square: closed,
+                                                                       owner: Player::O }));
+         assert_eq!(b.check_move(1).unwrap_err().to_string(), "a1-c3 was won by O");
+         assert_eq!(b.check_move(80), Ok(()));
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         for i in [0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23] {
+             assert!(b.make_move(i).is_ok());
+         }
+         assert_eq!(b.check_move(80), Err(IllegalReason::GameOver));
      }
 
      #[test]
      fn test_basic_victory_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23];
          for i in moves {
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
@@ -570,10 +995,10 @@ mod tests {
      }
      #[test]
      fn test_undo_basic_victory_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23];
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
@@ -581,21 +1006,40 @@ mod tests {
              b.undo_move();
          }
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
          assert!(b.winner == Player::O);
      }
 
+     #[test]
+     fn test_no_moves_after_game_over_2lv() {
+         // X's last move wins and sends O to a closed small board,
+         // which would let O play anywhere if the game weren't over
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         let moves = vec![57, 30, 31, 44, 77, 50, 49, 43, 69, 62, 76, 41, 48, 34, 68, 46, 9,
+                          1, 13, 36, 5, 51, 58, 39, 28, 16, 63, 6, 59, 53, 78, 64, 17, 80,
+                          73, 74, 23, 45, 2, 20, 22, 40, 3, 33, 72, 8, 79, 67];
+         for i in &moves {
+             assert!(b.make_move(*i).is_ok());
+         }
+         assert!(!b.get_moves().is_empty());
+         assert!(b.make_move(66).is_ok());
+         assert!(b.winner == Player::X);
+         assert!(b.get_moves().is_empty());
+         b.undo_move();
+         assert!(b.get_moves().contains(&66));
+     }
+
      #[test]
      fn test_full_square_ascend_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 1, 10, 9, 5, 45, 7, 70, 71, 80, 72, 4, 36, 8, 
                           73, 11, 18, 2, 20, 21, 27, 3, 33, 54, 6, 
                           61, 63, 13];
          for i in moves {
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
@@ -603,13 +1047,13 @@ mod tests {
 
      #[test]
      fn test_draw_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 1, 9, 4, 36, 7, 70, 71, 79, 67, 43, 63, 20, 21, 
                           31, 40, 37, 13, 38, 23, 49, 22, 10, 14, 52, 55, 11, 
                           50, 46, 30, 29, 27, 32, 33, 58, 78, 59, 72, 57, 73, 74, 
                           76, 77, 80];
          for i in moves {
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
@@ -618,41 +1062,41 @@ mod tests {
 
      #[test]
      fn test_basic_undo_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![20, 22, 38, 21, 29, 23, 50, 49, 41, 46, 14, 52];
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              assert!(b.undo_move());
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", *i);
          }
-         assert!(b.make_move(68));
-         assert!(!b.make_move(48));
+         assert!(b.make_move(68).is_ok());
+         assert!(b.make_move(48).is_err());
 
          for _i in 0..(moves.len()+1) {
              assert!(b.undo_move());
          }
 
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              assert!(b.undo_move());
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", *i);
          }
-         assert!(b.make_move(68));
-         assert!(!b.make_move(48));
+         assert!(b.make_move(68).is_ok());
+         assert!(b.make_move(48).is_err());
      }
 
      #[test]
      fn test_basic_victory_undo_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23];
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              assert!(b.undo_move());
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", *i);
          }
@@ -662,9 +1106,9 @@ mod tests {
          }
 
          for i in &moves {
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              assert!(b.undo_move());
-             assert!(b.make_move(*i));
+             assert!(b.make_move(*i).is_ok());
              b.pretty_print();
              println!("move: {}", *i);
          }
@@ -673,19 +1117,249 @@ mod tests {
 
      #[test]
      fn test_full_square_ascend_undo_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let moves = vec![0, 1, 10, 9, 5, 45, 7, 70, 71, 80, 72, 4, 36, 8, 
                           73, 11, 18, 2, 20, 21, 27, 3, 33, 54, 6, 
                           61, 63, 13];
          for i in moves {
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              assert!(b.undo_move());
-             assert!(b.make_move(i));
+             assert!(b.make_move(i).is_ok());
              b.pretty_print();
              println!("move: {}", i);
          }
      }
 
+     #[test]
+     fn test_dead_squares_2lv() {
+         // Leaves small board 0 as
+         // X O X
+         // X O O
+         // O X -
+         // where no line can be completed, but the last move
+         // is needed to block X's diagonal
+         let moves = vec![3, 27, 7, 63, 2, 18, 0, 4, 36, 6, 54, 1, 9, 5];
+         let sqr = Square { top_left: 0, level: 1 };
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         for i in &moves[..moves.len() - 1] {
+             assert!(b.make_move(*i).is_ok());
+         }
+         assert!(b.can_win(sqr, Player::X).unwrap());
+         assert!(!b.can_win(sqr, Player::O).unwrap());
+         assert!(!b.is_dead(sqr).unwrap());
+         assert!(b.make_move(5).is_ok());
+         assert!(b.is_dead(sqr).unwrap());
+         // Without dead boards it stays open
+         assert_eq!(b.get(sqr), Ok(Player::NEITHER));
+         let mut b = Board::new(2, Rules { dead_boards: true, ..Rules::default() }).unwrap();
+         for i in &moves {
+             assert!(b.make_move(*i).is_ok());
+         }
+         assert_eq!(b.get(sqr), Ok(Player::DEAD));
+         assert!(b.get_moves().iter().all(|m| *m >= 9));
+         // Undoing the move that killed it revives it
+         assert!(b.undo_move());
+         assert_eq!(b.get(sqr), Ok(Player::NEITHER));
+         assert!(!b.is_dead(sqr).unwrap());
+         assert!(!b.is_dead(Square { top_left: 0, level: 2 }).unwrap());
+     }
+
+     #[test]
+     fn test_dead_board_ends_game_2lv() {
+         // With dead boards, every game that ends in a draw ends
+         // no later than it would have otherwise, and games that
+         // are won are won the same way
+         let mut rng = StdRng::seed_from_u64(0);
+         let top = Square { top_left: 0, level: 2 };
+         let mut shorter = 0;
+         for _i in 0..200 {
+             let mut b = Board::new(2, Rules::default()).unwrap();
+             let mut dead = Board::new(2, Rules { dead_boards: true, ..Rules::default() }).unwrap();
+             let mut diverged = false;
+             while b.winner == Player::NEITHER && dead.winner == Player::NEITHER {
+                 let moves = dead.get_moves();
+                 let m = moves[rng.gen_range(0, moves.len())];
+                 assert!(dead.make_move(m).is_ok());
+                 if b.make_move(m).is_err() {
+                     // Only possible once the boards disagree on
+                     // where the next move goes
+                     diverged = true;
+                     break;
+                 }
+             }
+             if diverged {
+                 continue;
+             }
+             if dead.winner == Player::DEAD && b.winner == Player::NEITHER {
+                 assert!(b.is_dead(top).unwrap());
+                 shorter += 1;
+             }
+             if b.winner == Player::X || b.winner == Player::O {
+                 assert_eq!(dead.winner, b.winner);
+             }
+         }
+         assert!(shorter > 0);
+     }
+
+     #[test]
+     fn test_most_squares_tie_break_2lv() {
+         // The drawn game of test_draw_2lv, where O captured
+         // five small boards and X four
+         let rules = Rules { tie_break: TieBreak::MostSquares, ..Rules::default() };
+         let mut b = Board::new(2, rules).unwrap();
+         let moves = vec![0, 1, 9, 4, 36, 7, 70, 71, 79, 67, 43, 63, 20, 21,
+                          31, 40, 37, 13, 38, 23, 49, 22, 10, 14, 52, 55, 11,
+                          50, 46, 30, 29, 27, 32, 33, 58, 78, 59, 72, 57, 73, 74,
+                          76, 77, 80];
+         for i in &moves {
+             assert!(b.make_move(*i).is_ok());
+         }
+         assert_eq!(b.winner, Player::O);
+         assert!(b.undo_move());
+         assert_eq!(b.winner, Player::NEITHER);
+         assert!(b.make_move(80).is_ok());
+         assert_eq!(b.winner, Player::O);
+     }
+
+     #[test]
+     fn test_drawn_squares_count_for_both_2lv() {
+         // X completes the diagonal 2 4 6 of small boards, where
+         // small board 4 is drawn
+         let moves = vec![30, 29, 23, 52, 64, 9, 7, 68, 48, 28, 17, 80, 79, 67,
+                          38, 21, 35, 73, 16, 70, 69, 54, 1, 11, 26, 77, 45, 3,
+                          27, 4, 44, 78, 61, 71, 76, 42, 62, 75, 34, 63, 5, 46,
+                          10, 13, 40, 37, 14, 51, 60, 22, 43, 41, 53, 74, 25, 36,
+                          2, 20, 24, 0, 8, 31, 39];
+         let mut nobody = Board::new(2, Rules::default()).unwrap();
+         let mut both = Board::new(2, Rules { drawn_squares: DrawnSquares::Both,
+                                              ..Rules::default() }).unwrap();
+         for i in &moves {
+             assert!(nobody.make_move(*i).is_ok());
+             assert!(both.make_move(*i).is_ok());
+         }
+         assert_eq!(nobody.get(Square { top_left: 36, level: 1 }), Ok(Player::DEAD));
+         assert_eq!(nobody.winner, Player::NEITHER);
+         assert_eq!(both.winner, Player::X);
+         // A drawn square counts towards lines that can still be won
+         assert!(both.undo_move());
+         assert!(both.can_win(Square { top_left: 0, level: 2 }, Player::X).unwrap());
+     }
+
+     #[test]
+     fn test_free_move_any_board_2lv() {
+         // O captures small board 0 and X then sends O there
+         let moves = vec![0, 3, 27, 4, 36, 5, 45];
+         let mut open = Board::new(2, Rules::default()).unwrap();
+         let mut any = Board::new(2, Rules { free_move: FreeMove::AnyBoard,
+                                             ..Rules::default() }).unwrap();
+         for i in &moves {
+             assert!(open.make_move(*i).is_ok());
+             assert!(any.make_move(*i).is_ok());
+         }
+         let sqr = Square { top_left: 0, level: 1 };
+         assert!(!open.get_moves().contains(&1));
+         assert!(open.make_move(1).is_err());
+         assert!(any.get_moves().contains(&1));
+         assert!(any.make_move(1).is_ok());
+         assert_eq!(any.get(sqr), Ok(Player::O));
+         assert_eq!(any.next_legal, Square { top_left: 9, level: 1 });
+         // Sending to an open square still restricts the move
+         assert!(any.make_move(2).is_err());
+         assert!(any.undo_move());
+         assert_eq!(any.get(sqr), Ok(Player::O));
+         assert_eq!(any.get(Square { top_left: 1, level: 0 }), Ok(Player::NEITHER));
+     }
+
+     #[test]
+     fn test_validate_random_games_2lv() {
+         let mut rng = StdRng::seed_from_u64(0);
+         for tie_break in [TieBreak::Draw, TieBreak::MostSquares].iter() {
+         for drawn_squares in [DrawnSquares::Nobody, DrawnSquares::Both].iter() {
+         for free_move in [FreeMove::OpenBoards, FreeMove::AnyBoard].iter() {
+         for dead_boards in [false, true].iter() {
+             let rules = Rules {
+                 tie_break: *tie_break,
+                 drawn_squares: *drawn_squares,
+                 free_move: *free_move,
+                 dead_boards: *dead_boards,
+             };
+             for _i in 0..10 {
+                 let mut b = Board::new(2, rules).unwrap();
+                 assert_eq!(b.validate(), Ok(()));
+                 while b.winner == Player::NEITHER {
+                     let moves = b.get_moves();
+                     let m = moves[rng.gen_range(0, moves.len())];
+                     assert!(b.make_move(m).is_ok());
+                     assert_eq!(b.validate(), Ok(()), "{:?} after {}", rules, m);
+                     assert!(b.undo_move());
+                     assert_eq!(b.validate(), Ok(()), "{:?} undoing {}", rules, m);
+                     assert!(b.make_move(m).is_ok());
+                 }
+             }
+         }
+         }
+         }
+         }
+     }
+
+     #[cfg(feature = "serde")]
+     #[test]
+     fn test_serde_2lv() {
+         let rules = Rules { tie_break: TieBreak::MostSquares, ..Rules::default() };
+         let mut b = Board::new(2, rules).unwrap();
+         for i in [40, 36, 0] {
+             assert!(b.make_move(i).is_ok());
+         }
+         let json = serde_json::to_value(&b).unwrap();
+         assert_eq!(json["rules"]["tie_break"], "most-squares");
+         assert_eq!(json["rules"]["free_move"], "open-boards");
+         assert_eq!(json["moves"], serde_json::json!([40, 36, 0]));
+         assert_eq!(json["to_move"], "O");
+         assert_eq!(json["winner"], "NEITHER");
+         assert_eq!(json["next_legal"], serde_json::json!({ "top_left": 0, "level": 1 }));
+         assert_eq!(json["spaces"][40], "X");
+         assert_eq!(json["spaces"].as_array().unwrap().len(), 81);
+         assert_eq!(json["squares"].as_array().unwrap().len(), 9);
+         assert_eq!(serde_json::from_value::<Board>(json).unwrap(), b);
+         // Only the moves are needed, under the standard rules
+         // unless others are given
+         let read: Board = serde_json::from_str(r#"{"moves": [40, 36, 0]}"#).unwrap();
+         assert_eq!(read.rules(), Rules::default());
+         assert_eq!(read.move_history, b.move_history);
+         assert!(serde_json::from_str::<Board>(r#"{"moves": [40, 40]}"#).is_err());
+         assert!(serde_json::from_str::<Board>(r#"{"moves": [81]}"#).is_err());
+         let t = serde_json::to_string(&b.move_history[0]).unwrap();
+         assert_eq!(t, r#"{"space":40,"capture":81,"bounds":{"top_left":0,"level":2}}"#);
+     }
+
+     #[test]
+     fn test_validate_finds_errors_2lv() {
+         let mut b = Board::new(2, Rules::default()).unwrap();
+         for i in [0, 3, 27, 4, 36, 5].iter() {
+             assert!(b.make_move(*i).is_ok());
+         }
+         assert_eq!(b.validate(), Ok(()));
+         let mut wrong = b.clone();
+         wrong.set(Square { top_left: 0, level: 1 }, Player::NEITHER);
+         assert!(wrong.validate().is_err());
+         let mut wrong = b.clone();
+         wrong.set(Square { top_left: 9, level: 1 }, Player::DEAD);
+         assert!(wrong.validate().is_err());
+         let mut wrong = b.clone();
+         wrong.next_legal = Square { top_left: 0, level: 2 };
+         assert!(wrong.validate().is_err());
+         let mut wrong = b.clone();
+         wrong.next_player();
+         assert!(wrong.validate().is_err());
+         let mut wrong = b.clone();
+         wrong.winner = Player::O;
+         assert!(wrong.validate().is_err());
+         let mut wrong = b.clone();
+         wrong.spaces[80] = Player::X;
+         assert!(wrong.validate().is_err());
+     }
+
+     #[allow(dead_code)]
      fn get_moves_at_depths(b: &mut Board, depth: usize, out: &mut Vec<usize>) {
          if depth == 0 {
              return;
@@ -695,7 +1369,7 @@ mod tests {
          out[out_len - depth] += moves.len();
          for m in moves {
              let mut next_b = b.clone();
-             assert!(next_b.make_move(m));
+             assert!(next_b.make_move(m).is_ok());
              get_moves_at_depths(&mut next_b, depth - 1, out);
          }
      }
@@ -708,19 +1382,19 @@ mod tests {
          let mut sum = 0;
          for m in moves {
              let mut next_b = b.clone();
-             assert!(next_b.make_move(m));
+             assert!(next_b.make_move(m).is_ok());
              sum += get_moves_at_depths_no_vector(&mut next_b, depth - 1);
          }
          return sum;
      }
 
+     #[allow(clippy::assertions_on_constants)]
      fn get_moves_at_depths_undo(b: &mut Board, depth: usize, out: &mut Vec<usize>) {
          let moves = b.get_moves();
          let out_len = out.len();
          out[out_len - depth] += moves.len();
          for m in &moves {
-             let temp = out[out_len - 1];
-             if !b.make_move(*m) {
+             if b.make_move(*m).is_err() {
                  b.pretty_print();
                  println!("{}", *m);
                  println!("{:?}", b.next_legal);
@@ -743,31 +1417,38 @@ mod tests {
          }
      }
 
-     /*fn get_moves_at_depths_thread(other_b: &Board, depth: usize, out: &mut Vec<usize>) {
-         if depth == 0 {
-             return;
-         }
-         let b = other_b.clone();
+     // Same as get_moves_at_depths_undo, but the subtree
+     // below each move is searched on its own thread
+     fn get_moves_at_depths_thread(b: &Board, depth: usize, out: &mut [usize]) {
          let moves = b.get_moves();
          let out_len = out.len();
          out[out_len - depth] += moves.len();
-         let threads = Vec::new();
+         if depth == 1 {
+             return;
+         }
+         let mut threads = Vec::new();
          for m in &moves {
-             assert!(b.make_move(*m));
-             get_moves_at_depths_undo(b, depth - 1, out);
-             assert!(b.undo_move());
-             if moves != b.get_moves() {
-                 println!("After undo: {:?}", b.get_moves());
-                 println!("Before undo: {:?}", moves);
-                 assert!(false);
+             let mut next_b = b.clone();
+             assert!(next_b.make_move(*m).is_ok());
+             threads.push(thread::spawn(move || {
+                 let mut next_out = vec![0; depth - 1];
+                 get_moves_at_depths_undo(&mut next_b, depth - 1, &mut next_out);
+                 next_out
+             }));
+         }
+         for t in threads {
+             for (i, n) in t.join().unwrap().iter().enumerate() {
+                 out[out_len - depth + 1 + i] += n;
              }
-         }sdfasdf
-     }*/
+         }
+     }
 
      #[test]
      #[ignore]
+     #[allow(unused_variables)]
+     #[allow(clippy::needless_range_loop)]
      fn test_move_gen_2lv() {
-         let mut b = Board::new(2);
+         let mut b = Board::new(2, Rules::default()).unwrap();
          let depth = 8; // actually depth + 1
          println!("Level\tMoves");
          let mut levels = Vec::new();
@@ -786,7 +1467,12 @@ mod tests {
              print!("\t");
              println!("{}", levels[i]);
          }
-         b = Board::new(2);
+         let mut thread_levels = vec![0; depth];
+         now = Instant::now();
+         get_moves_at_depths_thread(&b, depth, &mut thread_levels);
+         println!("Threaded search took {} seconds", now.elapsed().as_secs());
+         assert_eq!(levels, thread_levels);
+         b = Board::new(2, Rules::default()).unwrap();
          now = Instant::now();
          let lowest_depth_moves = get_moves_at_depths_no_vector(&mut b, depth);
          println!("Search took {} seconds", now.elapsed().as_secs());
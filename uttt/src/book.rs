@@ -0,0 +1,165 @@
+use crate::bitboard::BitBoard;
+use crate::error::Error;
+use rand::{Rng, RngCore};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+// A sequence of moves from the empty board and how often
+// it should be chosen relative to the other lines
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct BookLine {
+    pub weight: u32,
+    pub moves: Vec<usize>,
+}
+
+// An opening book. Engines use it to pick their first moves
+// and the tournament uses its lines as starting positions
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Book {
+    pub lines: Vec<BookLine>,
+}
+
+impl Default for Book {
+    fn default() -> Self {
+        Book::new()
+    }
+}
+
+impl Book {
+    // An empty book, which never has a move
+    pub fn new() -> Book {
+        Book { lines: Vec::new() }
+    }
+
+    // The book that always opens in the center space
+    pub fn center() -> Book {
+        Book { lines: vec![BookLine { weight: 1, moves: vec![40] }] }
+    }
+
+    pub fn load(path: &str) -> Result<Book, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("couldn't read {}: {}", path, e)))?;
+        return text.parse();
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        return fs::write(path, self.to_string())
+            .map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+    }
+
+    // Picks the next move after history among the lines that start
+    // with history, with probability proportional to the total
+    // weight of the lines continuing with each move. Returns None
+    // if no line continues history
+    pub fn lookup(&self, history: &[usize], rng: &mut dyn RngCore) -> Option<usize> {
+        let mut candidates: Vec<(usize, u64)> = Vec::new();
+        for line in &self.lines {
+            if line.weight == 0 || line.moves.len() <= history.len()
+                || !line.moves.starts_with(history) {
+                continue;
+            }
+            let m = line.moves[history.len()];
+            match candidates.iter_mut().find(|c| c.0 == m) {
+                Some(c) => c.1 += line.weight as u64,
+                None => candidates.push((m, line.weight as u64)),
+            }
+        }
+        let total: u64 = candidates.iter().map(|c| c.1).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut n = rng.gen_range(0, total);
+        for (m, weight) in candidates {
+            if n < weight {
+                return Some(m);
+            }
+            n -= weight;
+        }
+        return None;
+    }
+}
+
+// One line per book line: the weight followed by the moves.
+// Blank lines and everything after a # are ignored
+impl FromStr for Book {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Book, Error> {
+        let mut book = Book::new();
+        for (n, raw_line) in s.lines().enumerate() {
+            let line = raw_line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let weight = words.next().unwrap().parse::<u32>()
+                .map_err(|_| Error::Parse(format!("line {}: expected a weight", n + 1)))?;
+            let mut moves = Vec::new();
+            let mut board = BitBoard::new();
+            for w in words {
+                match w.parse::<usize>() {
+                    Ok(m) if m < 81 && board.get_moves() & (1 << m) != 0 => {
+                        board.make_move(1 << m);
+                        moves.push(m);
+                    },
+                    _ => return Err(Error::Parse(format!("line {}: illegal move {}", n + 1, w))),
+                }
+            }
+            book.lines.push(BookLine { weight, moves });
+        }
+        return Ok(book);
+    }
+}
+
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}", line.weight)?;
+            for m in &line.moves {
+                write!(f, " {}", m)?;
+            }
+            writeln!(f)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+     #[test]
+     fn test_parse_book() {
+         let book: Book = "# openings\n3 40 36\n1 40 44 # comment\n\n2 0\n".parse().unwrap();
+         assert_eq!(book.lines.len(), 3);
+         assert_eq!(book.lines[1], BookLine { weight: 1, moves: vec![40, 44] });
+         assert_eq!(book.to_string().parse::<Book>(), Ok(book));
+         // The move after 40 must be in the center small board
+         assert!("1 40 0".parse::<Book>().is_err());
+         assert!("1 81".parse::<Book>().is_err());
+         assert!("x 40".parse::<Book>().is_err());
+     }
+
+     #[test]
+     fn test_lookup() {
+         let book: Book = "3 40 36\n1 40 44\n0 40 38\n".parse().unwrap();
+         let mut rng = StdRng::seed_from_u64(0);
+         assert_eq!(book.lookup(&[], &mut rng), Some(40));
+         assert_eq!(book.lookup(&[40, 36], &mut rng), None);
+         assert_eq!(book.lookup(&[4], &mut rng), None);
+         let mut counts = [0; 81];
+         for _i in 0..400 {
+             counts[book.lookup(&[40], &mut rng).unwrap()] += 1;
+         }
+         assert_eq!(counts[38], 0);
+         assert!(counts[36] > 2 * counts[44]);
+         assert!(counts[44] > 0);
+     }
+}
@@ -0,0 +1,249 @@
+// Builds opening books from the results of games
+//
+// Every position reached in the first plies of the games is
+// counted once per game, keyed by the position itself so that
+// move orders reaching the same position share their results.
+// The book then follows the moves played from the empty board
+// while enough games reached the next position
+use crate::ai::AI;
+use crate::bitboard::BitBoard;
+use crate::book::{Book, BookLine};
+use crate::record::GameRecord;
+use crate::simplesearchcenter::SimpleSearchCenterAI;
+use crate::board::Player;
+use crate::error::Error;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+// Results of the games that reached a position
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Stats {
+    pub x_wins: u32,
+    pub draws: u32,
+    pub o_wins: u32,
+}
+
+impl Stats {
+    pub fn games(&self) -> u32 {
+        return self.x_wins + self.draws + self.o_wins;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "X {} D {} O {}", self.x_wins, self.draws, self.o_wins)
+    }
+}
+
+struct Position {
+    stats: Stats,
+    // Moves played from this position in any game
+    moves: u128,
+}
+
+// Plays games between two copies of a depth limited
+// SimpleSearchCenterAI using diagonal2, without a book. The first
+// random_plies moves of each game are random and recorded as its
+// opening. Game i is played with seed + i
+pub fn self_play(games: usize, depth: usize, random_plies: usize,
+                 seed: u64) -> Result<Vec<GameRecord>, Error> {
+    let name = format!("diagonal2_depth_{}", depth);
+    let mut result = Vec::with_capacity(games);
+    for i in 0..games {
+        let game_seed = seed.wrapping_add(i as u64);
+        let mut record = GameRecord::new(&name, &name, game_seed);
+        let mut rng = StdRng::seed_from_u64(game_seed);
+        let mut board = BitBoard::new();
+        while record.moves.len() < random_plies && board.get_winner() == 0 {
+            let m = BitBoard::random_move(board.get_moves(), &mut rng);
+            board.make_move(m);
+            record.moves.push(m.trailing_zeros() as usize);
+        }
+        record.opening = record.moves.len();
+        let (x_seed, o_seed) = GameRecord::ai_seeds(game_seed);
+        let mut ais: Vec<SimpleSearchCenterAI> = [x_seed, o_seed].iter().map(|s| {
+            let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(),
+                                                   depth.max(1), 1, 0);
+            ai.set_book(Book::new());
+            ai.set_seed(*s);
+            ai.set_opening(&record.moves);
+            ai
+        }).collect();
+        let mut last_move = -1;
+        while board.get_winner() == 0 {
+            let ai = &mut ais[if board.to_move == 1 { 0 } else { 1 }];
+            last_move = ai.get_move(last_move)?;
+            board.make_move(1 << last_move);
+            record.moves.push(last_move as usize);
+        }
+        record.winner = match board.get_winner() {
+            1 => Player::X,
+            -1 => Player::O,
+            _ => Player::DEAD,
+        };
+        result.push(record);
+    }
+    return Ok(result);
+}
+
+// Writes games in the format read by GameRecord::load_all
+pub fn save_records(records: &[GameRecord], path: &str) -> Result<(), Error> {
+    let mut text = String::new();
+    for r in records {
+        text += &format!("{}\n", r);
+    }
+    return fs::write(path, text).map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+}
+
+// Builds a book of lines up to plies moves long from records.
+// A line is only extended to positions reached by at least
+// min_games games, and positions reached by several move orders
+// are only followed along the first one found. Lines are weighted
+// by the number of games that reached their last position, and
+// returned with those games' results
+pub fn build(records: &[GameRecord], plies: usize,
+             min_games: u32) -> Result<Vec<(BookLine, Stats)>, Error> {
+    let mut positions: HashMap<BitBoard, Position> = HashMap::new();
+    for r in records {
+        if r.winner == Player::NEITHER {
+            continue;
+        }
+        // Only count a position once per game
+        let mut seen = HashSet::new();
+        let mut board = BitBoard::new();
+        for m in r.moves.iter().take(plies) {
+            board.check_move(*m).map_err(|reason| Error::illegal_move(*m, reason))?;
+            let parent = board;
+            board.make_move(1 << m);
+            positions.entry(parent).or_insert(Position { stats: Stats::default(), moves: 0 })
+                .moves |= 1 << m;
+            if !seen.insert(board) {
+                continue;
+            }
+            let stats = &mut positions.entry(board)
+                .or_insert(Position { stats: Stats::default(), moves: 0 }).stats;
+            match r.winner {
+                Player::X => stats.x_wins += 1,
+                Player::O => stats.o_wins += 1,
+                _ => stats.draws += 1,
+            }
+        }
+    }
+    let mut lines = Vec::new();
+    let mut visited = HashSet::new();
+    let mut line = Vec::new();
+    extend(&positions, &BitBoard::new(), plies, min_games,
+           &mut visited, &mut line, &mut lines);
+    return Ok(lines);
+}
+
+// Depth first search from board adding a line for every position
+// where the book stops
+fn extend(positions: &HashMap<BitBoard, Position>, board: &BitBoard, plies: usize,
+          min_games: u32, visited: &mut HashSet<BitBoard>, line: &mut Vec<usize>,
+          lines: &mut Vec<(BookLine, Stats)>) {
+    let mut extended = false;
+    if line.len() < plies {
+        if let Some(p) = positions.get(board) {
+            BitBoard::iterate_moves(p.moves, &mut |m: u128, sf: i64| {
+                let mut next = *board;
+                next.make_move(m);
+                let enough = positions.get(&next)
+                    .is_some_and(|n| n.stats.games() >= min_games);
+                if !enough {
+                    return true;
+                }
+                // A transposition still counts as extending the line,
+                // since the position is continued by another line
+                extended = true;
+                if visited.insert(next) {
+                    line.push(sf as usize);
+                    extend(positions, &next, plies, min_games, visited, line, lines);
+                    line.pop();
+                }
+                return true;
+            });
+        }
+    }
+    if !extended && !line.is_empty() {
+        let stats = positions[board].stats;
+        lines.push((BookLine { weight: stats.games(), moves: line.clone() }, stats));
+    }
+}
+
+// The book file for lines, with each line's results
+// as a comment
+pub fn book_text(lines: &[(BookLine, Stats)]) -> String {
+    let mut text = String::from("# weight moves # results of the games reaching the line\n");
+    for (line, stats) in lines {
+        let book = Book { lines: vec![line.clone()] };
+        text += &format!("{} # {}\n", book.to_string().trim_end(), stats);
+    }
+    return text;
+}
+
+pub fn save_book(lines: &[(BookLine, Stats)], path: &str) -> Result<(), Error> {
+    return fs::write(path, book_text(lines))
+        .map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     fn record(moves: &[usize], winner: Player) -> GameRecord {
+         let mut r = GameRecord::new("a", "b", 0);
+         r.moves = moves.to_vec();
+         r.winner = winner;
+         return r;
+     }
+
+     #[test]
+     fn test_build_merges_transpositions() {
+         // 1 9 2 18 and 2 18 1 9 reach the same position
+         let records = vec![
+             record(&[1, 9, 2, 18, 0], Player::X),
+             record(&[2, 18, 1, 9, 3], Player::O),
+             record(&[2, 18, 1, 9, 4], Player::DEAD),
+         ];
+         let lines = build(&records, 4, 1).unwrap();
+         // The shared position after four moves ends only one line
+         assert_eq!(lines.len(), 1);
+         let (line, stats) = &lines[0];
+         assert_eq!(line.moves, vec![1, 9, 2, 18]);
+         assert_eq!(*stats, Stats { x_wins: 1, draws: 1, o_wins: 1 });
+         assert_eq!(line.weight, 3);
+         let text = book_text(&lines);
+         let book: Book = text.parse().unwrap();
+         assert_eq!(book.lines, vec![line.clone()]);
+     }
+
+     #[test]
+     fn test_min_games() {
+         let records = vec![
+             record(&[40, 36, 0], Player::X),
+             record(&[40, 36, 1], Player::O),
+             record(&[40, 44, 80], Player::O),
+         ];
+         let lines = build(&records, 3, 2).unwrap();
+         assert_eq!(lines.len(), 1);
+         assert_eq!(lines[0].0.moves, vec![40, 36]);
+         assert_eq!(lines[0].1, Stats { x_wins: 1, draws: 0, o_wins: 1 });
+         assert!(build(&[record(&[40, 0], Player::X)], 2, 1).is_err());
+     }
+
+     #[test]
+     fn test_self_play() {
+         let records = self_play(2, 1, 2, 5).unwrap();
+         assert_eq!(records.len(), 2);
+         assert_eq!(records[0].opening, 2);
+         assert_eq!(self_play(1, 1, 2, 5).unwrap()[0], records[0]);
+         assert!(!build(&records, 4, 1).unwrap().is_empty());
+     }
+}
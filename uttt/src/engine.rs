@@ -0,0 +1,500 @@
+use crate::ai::AI;
+use crate::board::{Board, Player};
+use crate::book::Book;
+use crate::error::Error;
+use crate::eval::{LinearEvaluator, Weights};
+use crate::mcts::{Budget, MctsAI};
+use crate::pipeai::EngineOption;
+use crate::rules::Rules;
+use crate::simplesearchcenter::SimpleSearchCenterAI;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+// Creates an AI from the values of the declared options
+pub type MakeAi = Box<dyn Fn(&[(EngineOption, String)]) -> Box<dyn AI>>;
+
+// Serves an AI to a driver over uttti (see PROTOCOL.md)
+//
+// The AI trait only hears about one move at a time, so the server
+// keeps the AI as long as every position it is sent continues the
+// game the AI has seen by one move, and starts a new AI from the
+// position whenever it doesn't. The times sent with go are
+// ignored, the AI decides how long to think
+//
+// Every server has a Rules option besides the declared ones, and
+// resigns when its AI can't play under the rules it is set to
+//
+// Commands are read by a thread of their own, so that stop can
+// reach an AI that is still thinking. A ponder search is an
+// ordinary search whose bestmove is held back until ponderhit or
+// stop
+pub struct EngineServer {
+    name: String,
+    author: String,
+    // The declared options and their current values
+    options: Vec<(EngineOption, String)>,
+    // The value of the Rules option
+    rules: Rules,
+    // Creates an AI using the current option values
+    make_ai: MakeAi,
+    ai: Option<Box<dyn AI>>,
+    // The moves of the game the AI has seen, including its own
+    known: Vec<usize>,
+    // The moves of the last position command, or None if
+    // they weren't a legal game that is still going on
+    position: Option<Vec<usize>>,
+    // Given to every AI with set_stop. Set by stop and
+    // cleared by go
+    stop: Arc<AtomicBool>,
+}
+
+impl EngineServer {
+    pub fn new(_name: &str, _author: &str, _options: Vec<EngineOption>,
+               _make_ai: MakeAi) -> EngineServer {
+        EngineServer {
+            name: _name.to_string(),
+            author: _author.to_string(),
+            options: _options.into_iter()
+                .map(|o| {
+                    let value = o.default.clone().unwrap_or_default();
+                    (o, value)
+                })
+                .collect(),
+            rules: Rules::default(),
+            make_ai: _make_ai,
+            ai: None,
+            known: Vec::new(),
+            position: Some(Vec::new()),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // The value of the option called name, if it is set
+    // and parses as a T
+    pub fn option<T: FromStr>(options: &[(EngineOption, String)], name: &str) -> Option<T> {
+        return options.iter()
+            .find(|(o, _)| o.name == name)
+            .and_then(|(_, v)| v.parse().ok());
+    }
+
+    // Answers the commands read from input until quit or the
+    // end of input
+    pub fn run(&mut self, input: &mut (dyn BufRead + Send),
+               output: &mut dyn Write) -> io::Result<()> {
+        let (sender, commands) = channel();
+        let stop = Arc::clone(&self.stop);
+        return thread::scope(|s| {
+            s.spawn(move || EngineServer::read_commands(input, &stop, sender));
+            // The move of a ponder search, sent on ponderhit or stop
+            let mut pondered = None;
+            for line in commands {
+                let line = line?;
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words.as_slice() {
+                    ["uttti"] => self.handshake(output)?,
+                    ["isready"] => writeln!(output, "readyok")?,
+                    ["newgame"] => self.ai = None,
+                    ["setoption", "name", rest @ ..] => self.option_command(rest),
+                    ["position", "moves", moves @ ..] => {
+                        self.position = match EngineServer::game(moves, self.rules) {
+                            Ok(game) => Some(game),
+                            Err(why) => {
+                                eprintln!("bad position: {}", why);
+                                None
+                            },
+                        };
+                    },
+                    ["go", rest @ ..] => {
+                        let m = self.best_move();
+                        if rest.contains(&"ponder") {
+                            pondered = Some(m);
+                        } else {
+                            self.write_best_move(output, m)?;
+                        }
+                    },
+                    ["ponderhit"] | ["stop"] => {
+                        if let Some(m) = pondered.take() {
+                            self.write_best_move(output, m)?;
+                        }
+                    },
+                    ["quit"] => break,
+                    _ => (),
+                }
+                output.flush()?;
+            }
+            if let Some(ai) = self.ai.as_mut() {
+                ai.cleanup();
+            }
+            return Ok(());
+        });
+    }
+
+    // Sends the lines of input to commands until quit or the end
+    // of input, setting stop on stop and clearing it on go
+    fn read_commands(input: &mut (dyn BufRead + Send), stop: &AtomicBool,
+                     commands: Sender<io::Result<String>>) {
+        loop {
+            let mut line = String::new();
+            let read = input.read_line(&mut line);
+            let command = line.split_whitespace().next().unwrap_or("");
+            match command {
+                "go" => stop.store(false, Ordering::Relaxed),
+                "stop" => stop.store(true, Ordering::Relaxed),
+                _ => (),
+            }
+            let last = command == "quit" || !matches!(read, Ok(n) if n > 0);
+            if commands.send(read.map(|_| line)).is_err() || last {
+                return;
+            }
+        }
+    }
+
+    fn handshake(&self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "id name {}", self.name)?;
+        writeln!(output, "id author {}", self.author)?;
+        for (o, _) in &self.options {
+            write!(output, "option name {} type {}", o.name, o.kind)?;
+            if let Some(d) = &o.default {
+                write!(output, " default {}", d)?;
+            }
+            if let (Some(min), Some(max)) = (o.min, o.max) {
+                write!(output, " min {} max {}", min, max)?;
+            }
+            writeln!(output)?;
+        }
+        writeln!(output, "option name Rules type string default {}", Rules::default())?;
+        return writeln!(output, "uttti ok");
+    }
+
+    // Sets the option named by the words after setoption name
+    // Sets the option called name, ignoring case, to value as
+    // setoption does. Rules is an option like the declared ones
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if name.eq_ignore_ascii_case("Rules") {
+            self.rules = value.parse().map_err(|why| {
+                Error::Invalid(format!("bad value {} for Rules: {}", value, why))
+            })?;
+            self.ai = None;
+            return Ok(());
+        }
+        let o = self.options.iter_mut()
+            .find(|(o, _)| o.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::Invalid(format!("no option named {}", name)))?;
+        o.1 = o.0.value(value)
+            .ok_or_else(|| Error::Invalid(format!("bad value {} for {}", value, o.0.name)))?;
+        // Options are given to the AI when it is created
+        self.ai = None;
+        return Ok(());
+    }
+
+    // A new AI using the current values of the declared options,
+    // to play the server's AI without a driver. It is left to
+    // the caller to set its rules
+    pub fn new_ai(&self) -> Box<dyn AI> {
+        return (self.make_ai)(&self.options);
+    }
+
+    fn option_command(&mut self, words: &[&str]) {
+        let value_at = words.iter().position(|w| *w == "value").unwrap_or(words.len());
+        let name = words[..value_at].join(" ");
+        let value = words.get(value_at + 1..).unwrap_or(&[]).join(" ");
+        if let Err(why) = self.set_option(&name, &value) {
+            eprintln!("{}", why);
+        }
+    }
+
+    fn write_best_move(&self, output: &mut dyn Write, m: i64) -> io::Result<()> {
+        if m == -1 {
+            return writeln!(output, "bestmove resign");
+        }
+        // Drivers use the score to adjudicate games
+        if let Some(ai) = self.ai.as_ref() {
+            if let Some(score) = ai.last_score() {
+                writeln!(output, "info score {}", score)?;
+            }
+            if let Some(reply) = ai.ponder_move() {
+                return writeln!(output, "bestmove {} ponder {}", m, reply);
+            }
+        }
+        return writeln!(output, "bestmove {}", m);
+    }
+
+    // The moves of a position command, or why they aren't
+    // a legal game under rules that isn't over
+    fn game(words: &[&str], rules: Rules) -> Result<Vec<usize>, Error> {
+        let mut board = Board::new(2, rules)?;
+        let mut moves = Vec::new();
+        for w in words {
+            let m = w.parse().map_err(|_| Error::Parse(format!("bad move {}", w)))?;
+            board.make_move(m)?;
+            moves.push(m);
+        }
+        if board.winner != Player::NEITHER {
+            return Err(Error::GameOver);
+        }
+        return Ok(moves);
+    }
+
+    // Asks the AI for its move in self.position, creating
+    // a new AI if the position doesn't continue its game.
+    // Resigns positions that aren't legal and when the AI fails
+    fn best_move(&mut self) -> i64 {
+        let position = match &self.position {
+            Some(p) => p.clone(),
+            None => {
+                self.ai = None;
+                return -1;
+            },
+        };
+        let continues = position.len() == self.known.len() + 1
+                        && position.starts_with(&self.known);
+        let result = match self.ai.as_mut() {
+            Some(ai) if continues => ai.get_move(*position.last().unwrap() as i64).unwrap_or(-1),
+            _ => {
+                let mut ai = self.new_ai();
+                ai.set_stop(Arc::clone(&self.stop));
+                let result = if ai.set_rules(self.rules)
+                                && (position.is_empty() || ai.set_opening(&position)) {
+                    ai.get_move(-1).unwrap_or(-1)
+                } else {
+                    -1
+                };
+                self.ai = Some(ai);
+                result
+            },
+        };
+        self.known = position;
+        if result == -1 {
+            self.ai = None;
+        } else {
+            self.known.push(result as usize);
+        }
+        return result;
+    }
+}
+
+// The server of uttt-engine --ai search: alpha-beta with a linear
+// evaluator using weights, playing from book if there is one. The
+// options are bounded so that every search ends within minutes
+pub fn search_server(weights: Weights, book: Option<Book>) -> EngineServer {
+    let options = vec![EngineOption::spin("Depth", 6, 1, 12),
+                       EngineOption::spin("Threads", 1, 1, 64),
+                       EngineOption::spin("SolveBelow", 0, 0, 36),
+                       EngineOption::spin("Seed", 0, 0, i64::MAX),
+                       EngineOption::check("Ponder", false)];
+    return EngineServer::new("uttt-search", "uttt", options,
+        Box::new(move |options: &[(EngineOption, String)]| -> Box<dyn AI> {
+            let mut ai = SimpleSearchCenterAI::new(
+                Box::new(LinearEvaluator::new(weights)),
+                EngineServer::option(options, "Depth").unwrap_or(6),
+                EngineServer::option(options, "Threads").unwrap_or(1),
+                EngineServer::option(options, "SolveBelow").unwrap_or(0));
+            if let Some(b) = &book {
+                ai.set_book(b.clone());
+            }
+            ai.set_seed(EngineServer::option(options, "Seed").unwrap_or(0));
+            ai.set_ponder(EngineServer::option(options, "Ponder").unwrap_or(false));
+            Box::new(ai)
+        }));
+}
+
+// The server of uttt-engine --ai mcts, bounded like search_server
+pub fn mcts_server() -> EngineServer {
+    let options = vec![EngineOption::spin("Playouts", 20000, 1, 1000000),
+                       EngineOption::spin("Threads", 1, 1, 64),
+                       EngineOption::string("Exploration", "1.4"),
+                       EngineOption::spin("Seed", 0, 0, i64::MAX)];
+    return EngineServer::new("uttt-mcts", "uttt", options,
+        Box::new(|options: &[(EngineOption, String)]| -> Box<dyn AI> {
+            let mut ai = MctsAI::new(
+                MctsAI::capture_playout(),
+                EngineServer::option(options, "Exploration").unwrap_or(1.4),
+                Budget::Iterations(EngineServer::option(options, "Playouts").unwrap_or(20000)),
+                EngineServer::option(options, "Threads").unwrap_or(1));
+            ai.set_seed(EngineServer::option(options, "Seed").unwrap_or(0));
+            Box::new(ai)
+        }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor, Read};
+    use std::time::{Duration, Instant};
+
+     // Plays the lowest empty space
+     struct LowestAI {
+         board: Vec<bool>,
+     }
+
+     impl AI for LowestAI {
+         fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+             if last_move != -1 {
+                 self.board[last_move as usize] = true;
+             }
+             let m = self.board.iter().position(|b| !b).unwrap();
+             self.board[m] = true;
+             return Ok(m as i64);
+         }
+
+         fn cleanup(&mut self) {}
+
+         fn set_opening(&mut self, moves: &[usize]) -> bool {
+             for m in moves {
+                 self.board[*m] = true;
+             }
+             return true;
+         }
+     }
+
+     // Input whose lines arrive one at a time, a pause apart
+     struct SlowInput {
+         lines: Vec<&'static str>,
+         pause: Duration,
+     }
+
+     impl Read for SlowInput {
+         fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+             if self.lines.is_empty() {
+                 return Ok(0);
+             }
+             thread::sleep(self.pause);
+             let line = self.lines.remove(0);
+             buf[..line.len()].copy_from_slice(line.as_bytes());
+             return Ok(line.len());
+         }
+     }
+
+     // Serves a LowestAI that starts after the spaces below Start
+     fn lowest_server() -> EngineServer {
+         let options = vec![EngineOption::spin("Start", 0, 0, 80)];
+         return EngineServer::new("lowest", "tests", options,
+             Box::new(|options: &[(EngineOption, String)]| -> Box<dyn AI> {
+                 let start: usize = EngineServer::option(options, "Start").unwrap();
+                 let mut board = vec![false; 81];
+                 for b in board.iter_mut().take(start) {
+                     *b = true;
+                 }
+                 Box::new(LowestAI { board })
+             }));
+     }
+
+     fn serve(input: &str) -> String {
+         let mut server = lowest_server();
+         let mut output = Vec::new();
+         server.run(&mut Cursor::new(input.as_bytes()), &mut output).unwrap();
+         return String::from_utf8(output).unwrap();
+     }
+
+     #[test]
+     fn test_handshake() {
+         assert_eq!(serve("uttti\nisready\nquit\nisready\n"),
+                    "id name lowest\nid author tests\n\
+                     option name Start type spin default 0 min 0 max 80\n\
+                     option name Rules type string default standard\nuttti ok\nreadyok\n");
+     }
+
+     #[test]
+     fn test_rules() {
+         // LowestAI only knows the standard game, and a bad value
+         // leaves the rules as they were
+         let output = serve("setoption name Rules value any-board\nposition moves 0 2 20\ngo\n\
+                             setoption name Rules value no-lines\nposition moves 0 2 20\ngo\n\
+                             setoption name Rules value standard\nposition moves 0 2 20\ngo\n");
+         assert_eq!(output, "bestmove resign\n".repeat(2) + "bestmove 1\n");
+     }
+
+     #[test]
+     fn test_option_bounds() {
+         // Values out of range are clamped and others ignored
+         let output = serve("setoption name Start value 1000\nposition moves\ngo\n\
+                             setoption name Start value -4\nposition moves\ngo\n\
+                             setoption name Start value 3\nsetoption name Start value x\n\
+                             position moves\ngo\n");
+         assert_eq!(output, "bestmove 80\nbestmove 0\nbestmove 3\n");
+     }
+
+     #[test]
+     fn test_set_option() {
+         let mut server = lowest_server();
+         assert_eq!(server.set_option("start", "5"), Ok(()));
+         assert_eq!(server.new_ai().get_move(-1), Ok(5));
+         assert!(matches!(server.set_option("Start", "x"), Err(Error::Invalid(_))));
+         assert!(matches!(server.set_option("Depth", "3"), Err(Error::Invalid(_))));
+         assert!(matches!(server.set_option("Rules", "no-lines"), Err(Error::Invalid(_))));
+         assert_eq!(server.set_option("rules", "any-board"), Ok(()));
+         assert_eq!(server.new_ai().get_move(-1), Ok(5));
+     }
+
+     #[test]
+     fn test_built_in_servers() {
+         // Options at the ends of their ranges, and beyond
+         let input = "uttti\nsetoption name Threads value 100000000\n\
+                      setoption name Depth value 0\nsetoption name Playouts value 0\n\
+                      setoption name Seed value -1\nsetoption name Ponder value maybe\n\
+                      position moves 40\ngo wtime 1000 btime 1000\nquit\n";
+         for mut server in [search_server(Weights::diagonal2(), None), mcts_server()] {
+             let mut output = Vec::new();
+             server.run(&mut Cursor::new(input.as_bytes()), &mut output).unwrap();
+             let output = String::from_utf8(output).unwrap();
+             assert!(output.contains(" min 1 max 64\n"));
+             let best = output.lines().last().unwrap();
+             let m: usize = best.split(' ').nth(1).unwrap().parse().unwrap();
+             assert!((36..45).contains(&m));
+         }
+     }
+
+     #[test]
+     fn test_ponder() {
+         // The bestmove of a ponder search waits for ponderhit or
+         // stop, and a stop without a search is ignored
+         let output = serve("stop\nposition moves\ngo ponder\nisready\nponderhit\n\
+                             position moves 0 1\ngo ponder wtime 10 btime 10\nstop\nstop\n");
+         assert_eq!(output, "readyok\nbestmove 0\nbestmove 2\n");
+     }
+
+     #[test]
+     fn test_stop() {
+         // Searches that would take minutes end soon after stop
+         let lines = vec!["setoption name Depth value 12\n",
+                          "setoption name Playouts value 1000000\n",
+                          "position moves 40\n", "go\n", "stop\n", "quit\n"];
+         for mut server in [search_server(Weights::diagonal2(), None), mcts_server()] {
+             let now = Instant::now();
+             let mut input = BufReader::new(SlowInput { lines: lines.clone(),
+                                                        pause: Duration::from_millis(100) });
+             let mut output = Vec::new();
+             server.run(&mut input, &mut output).unwrap();
+             assert!(now.elapsed() < Duration::from_secs(10));
+             let output = String::from_utf8(output).unwrap();
+             let best = output.lines().find(|l| l.starts_with("bestmove ")).unwrap();
+             let m: usize = best.split(' ').nth(1).unwrap().parse().unwrap();
+             assert!((36..45).contains(&m));
+         }
+     }
+
+     #[test]
+     fn test_positions() {
+         // The AI is kept while the game continues, so it remembers
+         // its own moves without being told
+         let output = serve("position moves\ngo\nposition moves 0 5\ngo\n\
+                             position moves 3 27\ngo\n\
+                             setoption name Start value 10\nposition moves\ngo\n");
+         assert_eq!(output, "bestmove 0\nbestmove 1\nbestmove 0\nbestmove 10\n");
+     }
+
+     #[test]
+     fn test_illegal_positions() {
+         // Out of range, repeated, in the wrong small board,
+         // unparsable and after the game is over
+         let output = serve("position moves 81\ngo\nposition moves 0 0\ngo\n\
+                             position moves 0 20\ngo\nposition moves 0 x\ngo\n\
+                             position moves 0 3 27 4 36 5 46 13 37 12 28 14 47 22 38 21 29 23\n\
+                             go\nposition moves 0\ngo\n");
+         assert_eq!(output, "bestmove resign\n".repeat(5) + "bestmove 1\n");
+     }
+}
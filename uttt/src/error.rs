@@ -0,0 +1,72 @@
+use crate::board::IllegalReason;
+use std::fmt;
+
+// Everything that can go wrong in the library. Programs built on it
+// that only report errors can turn them into a String with ?
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Error {
+    // A move that can't be made in the current position
+    IllegalMove { space: usize, reason: IllegalReason },
+    // A space, square or board size that doesn't exist. Signed
+    // so that negative moves are reported as they were given
+    OutOfRange { what: &'static str, value: i64 },
+    // A move was made after the game ended
+    GameOver,
+    // An engine's process couldn't be started
+    EngineSpawn { command: String, reason: String },
+    // Writing to or reading from an engine failed, for example
+    // because it exited
+    EngineIo { engine: String, reason: String },
+    // An engine didn't answer before its time ran out
+    EngineTimeout { engine: String },
+    // An AI can't do what it was asked to, like starting from
+    // an opening or pondering
+    Unsupported { ai: String, what: String },
+    // A file couldn't be read or written
+    Io(String),
+    // Text that isn't in the format it should be in
+    Parse(String),
+    // Settings that can't be used together, like an SPRT whose
+    // hypotheses are the wrong way round
+    Invalid(String),
+}
+
+impl Error {
+    // The error of a move on space that can't be made for reason.
+    // Moves off the board and after the game ended have errors
+    // of their own
+    pub fn illegal_move(space: usize, reason: IllegalReason) -> Error {
+        return match reason {
+            IllegalReason::OutOfRange => Error::OutOfRange { what: "space", value: space as i64 },
+            IllegalReason::GameOver => Error::GameOver,
+            _ => Error::IllegalMove { space, reason },
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::IllegalMove { space, reason } => write!(f, "illegal move {}: {}", space, reason),
+            Error::OutOfRange { what, value } => write!(f, "{} {} is out of range", what, value),
+            Error::GameOver => write!(f, "the game is over"),
+            Error::EngineSpawn { command, reason } =>
+                write!(f, "couldn't spawn {}: {}", command, reason),
+            Error::EngineIo { engine, reason } =>
+                write!(f, "couldn't talk to {}: {}", engine, reason),
+            Error::EngineTimeout { engine } => write!(f, "{} ran out of time", engine),
+            Error::Unsupported { ai, what } => write!(f, "{} can't {}", ai, what),
+            Error::Io(why) | Error::Parse(why) | Error::Invalid(why) => write!(f, "{}", why),
+        };
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for String {
+    fn from(e: Error) -> String {
+        return e.to_string();
+    }
+}
@@ -0,0 +1,273 @@
+use crate::bitboard::BitBoard;
+use crate::error::Error;
+use rand::{Rng, RngCore};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+// Scores positions for the search based AIs
+pub trait Evaluator: Send + Sync {
+    // Returns the score of board from the perspective of me,
+    // where me is 1 for X and -1 for O. Any randomness must
+    // come from rng so that games can be replayed from a seed
+    fn evaluate(&self, board: &mut BitBoard, me: i8, rng: &mut dyn RngCore) -> i32;
+}
+
+impl<F> Evaluator for F
+    where F: Fn(&mut BitBoard, i8, &mut dyn RngCore) -> i32 + Send + Sync {
+    fn evaluate(&self, board: &mut BitBoard, me: i8, rng: &mut dyn RngCore) -> i32 {
+        return self(board, me, rng);
+    }
+}
+
+// The features LinearEvaluator scores, in the order of
+// Weights::features. Each feature is counted for X minus
+// counted for O
+pub const FEATURE_NAMES: [&str; 7] = [
+    // Captured small boards
+    "captured_square",
+    // Captured center small board, on top of captured_square
+    "captured_center",
+    // Lines with two spaces taken and the third empty in small
+    // boards other than the center that are not captured yet
+    "two_in_row",
+    // The same in the center small board
+    "two_in_row_center",
+    // Lines of the big board with two small boards captured by
+    // the same player and the third not captured by the other
+    "macro_two_in_row",
+    // Occupied center spaces of small boards
+    "center_space",
+    // Occupied corner spaces of small boards
+    "corner_space",
+];
+
+pub const NUM_FEATURES: usize = 7;
+
+// The eight lines of a 3x3 board as masks of its nine subsquares
+static LINES: [u128; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+const CENTER_SPACES: u128 = 0x10 * 0x1008040201008040201;
+const CORNER_SPACES: u128 = 0x145 * 0x1008040201008040201;
+
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct Weights {
+    // Score of a won game
+    pub win: i32,
+    // Scores are randomly moved up or down by less than noise
+    // so that games between the same AIs differ
+    pub noise: i32,
+    pub features: [i32; NUM_FEATURES],
+}
+
+impl Weights {
+    // The weights of SimpleSearchCenterAI::abriand_eval_1
+    pub fn abriand_eval_1() -> Weights {
+        Weights {
+            win: 50000,
+            noise: 0,
+            features: [1000, 1000, 0, 0, 0, 100, 0],
+        }
+    }
+
+    // The weights of SimpleSearchCenterAI::diagonal
+    pub fn diagonal() -> Weights {
+        Weights {
+            win: 50000,
+            noise: 100,
+            features: [1000, 1000, 400, 800, 6000, 200, 100],
+        }
+    }
+
+    // The weights of SimpleSearchCenterAI::diagonal2
+    pub fn diagonal2() -> Weights {
+        Weights {
+            win: 50000,
+            noise: 100,
+            features: [1000, 1000, 400, 600, 6000, 200, 100],
+        }
+    }
+
+    // Reads weights written by save, or written by hand in
+    // the same format
+    pub fn load(path: &str) -> Result<Weights, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("couldn't read {}: {}", path, e)))?;
+        return text.parse();
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        return fs::write(path, self.to_string())
+            .map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+    }
+}
+
+// One "name value" pair per line, blank lines and everything
+// after a # are ignored. Weights that are not listed are zero
+impl FromStr for Weights {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Weights, Error> {
+        let mut weights = Weights { win: 0, noise: 0, features: [0; NUM_FEATURES] };
+        for (n, raw_line) in s.lines().enumerate() {
+            let line = raw_line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap();
+            let value = match (words.next().map(|v| v.parse::<i32>()), words.next()) {
+                (Some(Ok(v)), None) => v,
+                _ => return Err(Error::Parse(format!("line {}: expected a name and an integer",
+                                                     n + 1))),
+            };
+            match name {
+                "win" => weights.win = value,
+                "noise" => weights.noise = value,
+                _ => match FEATURE_NAMES.iter().position(|f| *f == name) {
+                    Some(i) => weights.features[i] = value,
+                    None => return Err(Error::Parse(format!("line {}: unknown weight {}",
+                                                            n + 1, name))),
+                },
+            }
+        }
+        return Ok(weights);
+    }
+}
+
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "win {}", self.win)?;
+        writeln!(f, "noise {}", self.noise)?;
+        for (name, value) in FEATURE_NAMES.iter().zip(self.features.iter()) {
+            writeln!(f, "{} {}", name, value)?;
+        }
+        return Ok(());
+    }
+}
+
+// Scores a position as the sum of its features times their weights
+pub struct LinearEvaluator {
+    pub weights: Weights,
+}
+
+impl LinearEvaluator {
+    pub fn new(_weights: Weights) -> LinearEvaluator {
+        LinearEvaluator { weights: _weights }
+    }
+
+    // Counts the lines of the 3x3 board given by mine and theirs
+    // where mine has two subsquares and theirs has none
+    fn two_in_row(mine: u128, theirs: u128) -> i32 {
+        let mut result = 0;
+        for line in LINES.iter() {
+            if (mine & line).count_ones() == 2 && theirs & line == 0 {
+                result += 1;
+            }
+        }
+        return result;
+    }
+
+    // Returns the features of board, counted for X minus
+    // counted for O
+    pub fn features(board: &BitBoard) -> [i32; NUM_FEATURES] {
+        let x = board.x_occupancy;
+        let o = board.o_occupancy;
+        let x_macro = (x >> 81) & 0x1ff;
+        let o_macro = (o >> 81) & 0x1ff;
+        let mut features = [0; NUM_FEATURES];
+        features[0] = x_macro.count_ones() as i32 - o_macro.count_ones() as i32;
+        features[1] = ((x_macro >> 4) & 1) as i32 - ((o_macro >> 4) & 1) as i32;
+        for i in 0..9 {
+            if (x_macro | o_macro) & (1 << i) != 0 {
+                continue;
+            }
+            let x_small = (x >> (9 * i)) & 0x1ff;
+            let o_small = (o >> (9 * i)) & 0x1ff;
+            let lines = LinearEvaluator::two_in_row(x_small, o_small)
+                        - LinearEvaluator::two_in_row(o_small, x_small);
+            if i == 4 {
+                features[3] += lines;
+            } else {
+                features[2] += lines;
+            }
+        }
+        features[4] = LinearEvaluator::two_in_row(x_macro, o_macro)
+                      - LinearEvaluator::two_in_row(o_macro, x_macro);
+        features[5] = (x & CENTER_SPACES).count_ones() as i32
+                      - (o & CENTER_SPACES).count_ones() as i32;
+        features[6] = (x & CORNER_SPACES).count_ones() as i32
+                      - (o & CORNER_SPACES).count_ones() as i32;
+        return features;
+    }
+
+    // The score of board from X's perspective, without noise
+    pub fn score(&self, board: &BitBoard) -> i32 {
+        match board.get_winner() {
+            1 => return self.weights.win,
+            -1 => return -self.weights.win,
+            _ => (),
+        }
+        let features = LinearEvaluator::features(board);
+        let mut result = 0;
+        for (feature, weight) in features.iter().zip(self.weights.features.iter()) {
+            result += feature * weight;
+        }
+        return result;
+    }
+}
+
+impl Evaluator for LinearEvaluator {
+    fn evaluate(&self, board: &mut BitBoard, me: i8, rng: &mut dyn RngCore) -> i32 {
+        let mut result = (me as i32) * self.score(board);
+        if self.weights.noise > 0 && board.get_winner() == 0 {
+            result += rng.gen_range(-self.weights.noise, self.weights.noise);
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_parse_weights() {
+         let text = "# a comment\nwin 100\n\ntwo_in_row 7 # trailing comment\n";
+         let weights: Weights = text.parse().unwrap();
+         assert_eq!(weights.win, 100);
+         assert_eq!(weights.features, [0, 0, 7, 0, 0, 0, 0]);
+         assert!("two_in_row".parse::<Weights>().is_err());
+         assert!("bogus 1".parse::<Weights>().is_err());
+         let diagonal2 = Weights::diagonal2();
+         assert_eq!(diagonal2.to_string().parse::<Weights>(), Ok(diagonal2));
+         assert_eq!(include_str!("../weights/diagonal2.txt").parse::<Weights>(), Ok(diagonal2));
+     }
+
+     #[test]
+     fn test_features() {
+         // X has captured small board 0 and has two in a row in
+         // small board 4, O has the centers of small boards 1 and 3
+         let mut board = BitBoard::new();
+         board.x_occupancy = (1 << 0) | (1 << 4) | (1 << 8) | (1 << 81) | (1 << 36) | (1 << 40);
+         board.o_occupancy = (1 << 13) | (1 << 31);
+         let features = LinearEvaluator::features(&board);
+         assert_eq!(features[0], 1);
+         assert_eq!(features[1], 0);
+         assert_eq!(features[3], 1);
+         assert_eq!(features[2], 0);
+         assert_eq!(features[5], 0);
+         assert_eq!(features[6], 3);
+     }
+}
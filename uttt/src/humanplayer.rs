@@ -1,4 +1,6 @@
 pub use crate::board::Board;
+use crate::error::Error;
+use crate::rules::Rules;
 pub use crate::ai::AI;
 use text_io::read;
 
@@ -7,9 +9,9 @@ pub struct HumanPlayer {
 }
 
 impl AI for HumanPlayer {
-    fn get_move(&mut self, last_move : i64) -> i64 {
+    fn get_move(&mut self, last_move : i64) -> Result<i64, Error> {
         if last_move != -1 {
-            self.board.make_move(last_move as usize);
+            self.board.make_move(last_move as usize)?;
         }
         self.board.pretty_print();
         loop {
@@ -17,22 +19,43 @@ impl AI for HumanPlayer {
             println!("Enter a square or 900 to resign: ");
             let i: usize = read!();
             if i == 900 {
-                return -1;
+                return Ok(-1);
             } else {
-                if self.board.make_move(i as usize) {
-                    return i as i64;
-                } else {
-                    println!("{} is an illegal move", i);
+                match self.board.make_move(i) {
+                    Ok(()) => return Ok(i as i64),
+                    Err(why) => println!("{}", why),
                 }
             }
         }
     }
 
     fn cleanup(&mut self) {}
+
+    fn set_rules(&mut self, rules: Rules) -> bool {
+        if !self.board.move_history.is_empty() {
+            return false;
+        }
+        return match Board::new(2, rules) {
+            Ok(board) => {
+                self.board = board;
+                true
+            },
+            Err(_) => false,
+        };
+    }
+
+    fn set_opening(&mut self, moves: &[usize]) -> bool {
+        for m in moves {
+            if self.board.make_move(*m).is_err() {
+                return false;
+            }
+        }
+        return true;
+    }
 }
 
 impl HumanPlayer {
-    pub fn new(max_level_: usize) -> HumanPlayer {
-        HumanPlayer { board: Board::new(max_level_) }
+    pub fn new(max_level_: usize, rules: Rules) -> Result<HumanPlayer, Error> {
+        return Ok(HumanPlayer { board: Board::new(max_level_, rules)? });
     }
 }
@@ -0,0 +1,159 @@
+// Tournament results as JSON Lines, one object per line, for
+// scripts that analyze them. See JSON.md for the fields. Written
+// by hand so that the serde feature isn't needed to produce it
+use crate::board::Player;
+use crate::error::Error;
+use crate::record::GameRecord;
+use crate::sprt::{Decision, Sprt};
+use crate::tournament::{Format, Standing};
+use std::fs;
+use std::io::Write;
+
+// A JSON string literal for text
+fn string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
+
+// A JSON array of values, with None as null
+fn array<T: ToString>(values: impl Iterator<Item = Option<T>>) -> String {
+    let values: Vec<String> = values.map(|v| match v {
+        Some(v) => v.to_string(),
+        None => "null".to_string(),
+    }).collect();
+    return format!("[{}]", values.join(","));
+}
+
+fn player(p: Player) -> &'static str {
+    return match p {
+        Player::X => "\"X\"",
+        Player::O => "\"O\"",
+        Player::DEAD => "\"DEAD\"",
+        Player::NEITHER => "\"NEITHER\"",
+    };
+}
+
+// The result of a game from X's side: 1-0, 0-1, 1/2-1/2, or *
+// for a game that wasn't played
+pub fn result(winner: Player) -> &'static str {
+    return match winner {
+        Player::X => "1-0",
+        Player::O => "0-1",
+        Player::DEAD => "1/2-1/2",
+        Player::NEITHER => "*",
+    };
+}
+
+// The line for one game. Its fields are those of the record
+// followed by the result
+pub fn game(record: &GameRecord) -> String {
+    return format!("{{\"type\":\"game\",\"x\":{},\"o\":{},\"seed\":{},\"opening\":{},\
+                    \"moves\":{},\"times\":{},\"scores\":{},\"winner\":{},\
+                    \"termination\":\"{}\",\"result\":\"{}\"}}",
+                   string(&record.x), string(&record.o), record.seed, record.opening,
+                   array(record.moves.iter().map(Some)),
+                   array(record.times.iter().map(Some)),
+                   array(record.scores.iter().copied()),
+                   player(record.winner), record.termination, result(record.winner));
+}
+
+// The line summing up a tournament of games played in format,
+// starting from first_seed, with the standings after them
+pub fn summary(format: Format, first_seed: u64, games: usize, standings: &[Standing]) -> String {
+    let standings: Vec<String> = standings.iter().map(|s| {
+        format!("{{\"name\":{},\"games\":{},\"wins\":{},\"draws\":{},\"losses\":{},\
+                 \"score\":{}}}",
+                string(&s.name), s.games, s.wins, s.draws, s.losses, s.score)
+    }).collect();
+    return format!("{{\"type\":\"summary\",\"format\":\"{}\",\"games\":{},\"first_seed\":{},\
+                    \"standings\":[{}]}}",
+                   format, games, first_seed, standings.join(","));
+}
+
+// The line with the state of an SPRT after pairs, counted as
+// for Sprt::score, and what it decided
+pub fn sprt(sprt: &Sprt, pairs: &[u32; 5], decision: Decision) -> String {
+    let (lower, upper) = sprt.bounds();
+    return format!("{{\"type\":\"sprt\",\"elo0\":{},\"elo1\":{},\"alpha\":{},\"beta\":{},\
+                    \"pairs\":{},\"llr\":{},\"lower\":{},\"upper\":{},\"result\":\"{}\"}}",
+                   sprt.elo0, sprt.elo1, sprt.alpha, sprt.beta,
+                   array(pairs.iter().map(Some)), sprt.llr(pairs), lower, upper, decision);
+}
+
+// Appends line to the file at path, creating it if needed
+pub fn append(path: &str, line: &str) -> Result<(), Error> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)
+        .map_err(|e| Error::Io(format!("couldn't open {}: {}", path, e)))?;
+    return writeln!(file, "{}", line)
+        .map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Termination;
+
+     #[test]
+     fn test_game_line() {
+         let mut record = GameRecord::new("diagonal2", "say \"hi\"\n", 12345);
+         record.opening = 1;
+         record.moves = vec![40, 36, 4];
+         record.times = vec![250, 7];
+         record.scores = vec![Some(-30), None];
+         record.winner = Player::DEAD;
+         record.termination = Termination::AdjudicatedDraw;
+         assert_eq!(game(&record),
+                    "{\"type\":\"game\",\"x\":\"diagonal2\",\"o\":\"say \\\"hi\\\"\\n\",\
+                     \"seed\":12345,\"opening\":1,\"moves\":[40,36,4],\"times\":[250,7],\
+                     \"scores\":[-30,null],\"winner\":\"DEAD\",\
+                     \"termination\":\"adjudicated-draw\",\"result\":\"1/2-1/2\"}");
+         let json: serde_json::Value = serde_json::from_str(&game(&record)).unwrap();
+         assert_eq!(json["o"], "say \"hi\"\n");
+     }
+
+     #[test]
+     fn test_summary_line() {
+         let standings = vec![
+             Standing { name: "a".to_string(), games: 2, wins: 1, draws: 1, losses: 0,
+                        score: 1.5 },
+             Standing { name: "b".to_string(), games: 0, wins: 0, draws: 0, losses: 0,
+                        score: 0.0 },
+         ];
+         let line = summary(Format::Swiss, 0, 3, &standings);
+         let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+         assert_eq!(json["type"], "summary");
+         assert_eq!(json["format"], "swiss");
+         assert_eq!(json["games"], 3);
+         let a = &json["standings"][0];
+         assert_eq!(a["name"], "a");
+         assert_eq!(a["games"], 2);
+         assert_eq!(a["wins"], 1);
+         assert_eq!(a["draws"], 1);
+         assert_eq!(a["losses"], 0);
+         assert_eq!(a["score"], 1.5);
+         assert_eq!(json["standings"][1]["score"], 0);
+     }
+
+     #[test]
+     fn test_sprt_line() {
+         let line = sprt(&Sprt::default(), &[0, 1, 2, 3, 4], Decision::Continue);
+         let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+         assert_eq!(json["type"], "sprt");
+         assert_eq!(json["elo1"], 5.0);
+         assert_eq!(json["pairs"], serde_json::json!([0, 1, 2, 3, 4]));
+         assert_eq!(json["result"], "none");
+     }
+}
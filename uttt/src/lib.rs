@@ -0,0 +1,40 @@
+#![allow(clippy::needless_return)]
+
+// Ultimate tic-tac-toe as a library: the board and its rule
+// variants, a notation for moves, the AI trait with the built-in
+// AIs, drivers for engines in other processes and a tournament
+// runner. The uttt binary is a command line front end to it and
+// uttt-engine serves the built-in AIs over uttti
+
+pub mod error;
+pub mod board;
+pub mod rules;
+pub mod notation;
+pub mod bitboard;
+pub mod simplesearchcenter;
+pub mod mcts;
+pub mod transposition;
+pub mod solver;
+pub mod eval;
+pub mod tune;
+pub mod record;
+pub mod jsonl;
+pub mod book;
+pub mod bookgen;
+
+pub mod ai;
+pub mod humanplayer;
+pub mod pipeai;
+pub mod engine;
+pub mod adjudication;
+pub mod tournament;
+pub mod sprt;
+
+pub use crate::ai::AI;
+pub use crate::board::{Board, IllegalReason, Player, Square};
+pub use crate::error::Error;
+pub use crate::engine::EngineServer;
+pub use crate::pipeai::PipeAI;
+pub use crate::record::GameRecord;
+pub use crate::rules::Rules;
+pub use crate::tournament::{Entrant, Tournament};
@@ -1,129 +1,313 @@
-mod board;
+#![allow(clippy::needless_return)]
 
-mod ai;
-use ai::AI;
-pub use board::Board;
-mod humanplayer;
-mod pipeai;
-pub use pipeai::PipeAI;
-pub use humanplayer::HumanPlayer;
-use board::Player;
-use std::time::Instant;
+use uttt::eval::Weights;
+use uttt::record::GameRecord;
+use uttt::adjudication::Adjudication;
+use uttt::book::Book;
+use uttt::tournament::{standings_table, Entrant, Format, Tournament};
+use uttt::sprt::Sprt;
+use uttt::{bookgen, tune, Error, Rules};
 
-use std::collections::HashMap;
+use structopt::StructOpt;
 
-/*#[derive(StructOpt)]
+#[derive(StructOpt)]
+#[structopt(name = "uttt")]
 struct Cli {
-    #[structopt(parse(from_os_str))]
-    o_ai_path: std::path::PathBuf,
-    #[structopt(parse(from_os_str))]
-    x_ai_path: std::path::PathBuf,
-}*/
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
 
-fn main() {
-    let ais: Vec<(String, Box<dyn Fn() -> Box<dyn AI>>)> = 
-        vec![
-            /*("javascript_10".to_string(),
-            Box::new(move || Box::new(
-                PipeAI::new("C:/Program Files/nodejs/node.exe".to_string(), 
-                vec!["uttt.js".to_string(), "10".to_string()])
-            ))
-            ),*/
-            ("abriand_10".to_string(),
-            Box::new(move || Box::new(
-                PipeAI::new("C:/Users/atb88/Desktop/uttt-bot/target/release/uttt-bot.exe".to_string(),
-                      vec![])))
-            ),
-            ("ggeng_10".to_string(),
-            Box::new(move || Box::new(
-                PipeAI::new("C:/ultimate-tictactoe/target/release/main.exe".to_string(),
-                      vec!["10".to_string()])))
-            ),
-        ];
-    let mut games: HashMap<(String, String), Player> = HashMap::new();
-    let mut scores: Vec<f32> = vec![0.0; ais.len()];
-    for _i in 0..1 {
-    for x_idx in 0..ais.len() {
-        for o_idx in 0..ais.len() {
-            if x_idx != o_idx {
-                let (o_name, o_ctor) = &ais[o_idx];
-                let (x_name, x_ctor) = &ais[x_idx];
-                match play_game(&mut *x_ctor(), &mut *o_ctor()) {
-                    Player::X => {
-                        scores[x_idx] += 1.0;
-                        games.insert((x_name.clone(), o_name.clone() + " " + &_i.to_string()), Player::X);
-                    },
-                    Player::O => {
-                        scores[o_idx] += 1.0;
-                        games.insert((x_name.clone(), o_name.clone() + " " + &_i.to_string()), Player::O);
-                    },
-                    Player::DEAD => {
-                        scores[x_idx] += 0.5;
-                        scores[o_idx] += 0.5;
-                        games.insert((x_name.clone(), o_name.clone() + " " + &_i.to_string()), Player::DEAD);
-                    },
-                    Player::NEITHER => panic!("NEITHER won"),
-                };
-            }
-        }
+#[derive(StructOpt)]
+enum Command {
+    /// Play a tournament between AIs (the default)
+    #[structopt(name = "tournament")]
+    Tournament(TournamentOptions),
+    /// Fit evaluation weights to the results of self-play games
+    #[structopt(name = "tune")]
+    Tune(TuneOptions),
+    /// Build an opening book from the results of games
+    #[structopt(name = "book")]
+    Book(BookOptions),
+}
+
+#[derive(StructOpt)]
+struct TournamentOptions {
+    /// Who plays whom: round-robin, gauntlet, swiss, knockout or sprt
+    #[structopt(long = "format", default_value = "round-robin")]
+    format: Format,
+    /// The rules the games are played under: standard, or a comma
+    /// separated list of the variants most-squares, drawn-for-both,
+    /// any-board and dead-boards. The tournament doesn't start if
+    /// an AI can't play under them
+    #[structopt(long = "rules", default_value = "standard")]
+    rules: Rules,
+    /// An AI that plays in the tournament, can be given more than
+    /// once: search[:<weights file>][:<option>=<value>,...],
+    /// mcts[:<option>=<value>,...] or pipe:<command>[ <argument>...],
+    /// where the options are those uttt-engine declares, like
+    /// search:tuned.txt:depth=8 or mcts:playouts=50000. The spec is
+    /// the AI's name. Defaults to search and mcts
+    #[structopt(long = "entrant")]
+    entrants: Vec<String>,
+    /// Number of times the schedule is played, for swiss the number
+    /// of rounds and for sprt the most pairs of games. Defaults to 1,
+    /// enough swiss rounds to leave one entrant who won every match,
+    /// or no limit for sprt
+    #[structopt(long = "rounds")]
+    rounds: Option<usize>,
+    /// The AI that plays every other in a gauntlet, or is tested
+    /// by sprt, as an --entrant spec that needn't be listed. The
+    /// first listed if not given
+    #[structopt(long = "candidate")]
+    candidate: Option<String>,
+    /// The AI the candidate is tested against by sprt, like
+    /// --candidate. The first other one listed if not given
+    #[structopt(long = "baseline")]
+    baseline: Option<String>,
+    /// The Elo difference the candidate is stronger by under H0 of
+    /// the SPRT
+    #[structopt(long = "elo0", default_value = "0")]
+    elo0: f64,
+    /// The Elo difference the candidate is stronger by under H1
+    #[structopt(long = "elo1", default_value = "5")]
+    elo1: f64,
+    /// The chance of the SPRT accepting H1 when H0 is true
+    #[structopt(long = "alpha", default_value = "0.05")]
+    alpha: f64,
+    /// The chance of the SPRT accepting H0 when H1 is true
+    #[structopt(long = "beta", default_value = "0.05")]
+    beta: f64,
+    /// Seed of the first game, later games use the following
+    /// seeds. Chosen at random if not given
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+    /// Append the record of every game to this file
+    #[structopt(long = "records")]
+    records: Option<String>,
+    /// Append a JSON line for every game and one with the final
+    /// standings to this file, see JSON.md
+    #[structopt(long = "json")]
+    json: Option<String>,
+    /// Keep the games already in the --records file and play only
+    /// the rest, to finish a tournament that was stopped. Its seed
+    /// is that of the first record unless --seed is given
+    #[structopt(long = "resume")]
+    resume: bool,
+    /// Book whose lines are played as openings, every pairing
+    /// plays each opening once with each AI as X
+    #[structopt(long = "openings")]
+    openings: Option<String>,
+    /// Let this AI think on its opponent's time, can be given more
+    /// than once. Every other AI only thinks on its own time. The
+    /// tournament doesn't start if the AI can't ponder
+    #[structopt(long = "ponder")]
+    ponder: Vec<String>,
+    /// Adjudicate a game as won once both AIs have reported a score of
+    /// at least this much for the same side for --adjudicate-moves moves
+    #[structopt(long = "adjudicate-score")]
+    adjudicate_score: Option<i32>,
+    /// Number of moves each AI must agree on the winner for
+    #[structopt(long = "adjudicate-moves", default_value = "4")]
+    adjudicate_moves: usize,
+    /// Adjudicate a game as drawn once neither side can complete a
+    /// line of small boards
+    #[structopt(long = "adjudicate-draws")]
+    adjudicate_draws: bool,
+}
+
+impl Default for TournamentOptions {
+    // The options of a tournament run without any arguments
+    fn default() -> Self {
+        return TournamentOptions::from_iter(&["tournament"]);
     }
+}
+
+#[derive(StructOpt)]
+struct TuneOptions {
+    /// Number of self-play games to generate
+    #[structopt(long = "games", default_value = "200")]
+    games: usize,
+    /// Search depth of the self-play AI
+    #[structopt(long = "depth", default_value = "2")]
+    depth: usize,
+    /// Number of random moves at the start of each self-play game
+    #[structopt(long = "random-plies", default_value = "8")]
+    random_plies: usize,
+    /// Weights file to start from instead of diagonal2
+    #[structopt(long = "weights")]
+    weights: Option<String>,
+    /// Tune on games from this file instead of generating them
+    #[structopt(long = "load-games")]
+    load_games: Option<String>,
+    /// Write the generated games to this file
+    #[structopt(long = "save-games")]
+    save_games: Option<String>,
+    /// Initial amount to change weights by
+    #[structopt(long = "step", default_value = "64")]
+    step: i32,
+    /// Seed for the random opening moves of the self-play games
+    #[structopt(long = "seed", default_value = "0")]
+    seed: u64,
+    /// Where to write the tuned weights
+    #[structopt(short = "o", long = "output", default_value = "tuned.txt")]
+    output: String,
+}
+
+#[derive(StructOpt)]
+struct BookOptions {
+    /// Number of self-play games to generate
+    #[structopt(long = "games", default_value = "100")]
+    games: usize,
+    /// Search depth of the self-play AI
+    #[structopt(long = "depth", default_value = "2")]
+    depth: usize,
+    /// Number of random moves at the start of each self-play game
+    #[structopt(long = "random-plies", default_value = "2")]
+    random_plies: usize,
+    /// Seed of the first self-play game
+    #[structopt(long = "seed", default_value = "0")]
+    seed: u64,
+    /// Build the book from game records in this file, such as those
+    /// written by tournament --records, instead of self-play
+    #[structopt(long = "load-records")]
+    load_records: Option<String>,
+    /// Write the self-play games to this file
+    #[structopt(long = "save-records")]
+    save_records: Option<String>,
+    /// Length of the book lines
+    #[structopt(long = "plies", default_value = "4")]
+    plies: usize,
+    /// Number of games that must reach a position for it to be in the book
+    #[structopt(long = "min-games", default_value = "3")]
+    min_games: u32,
+    /// Where to write the book
+    #[structopt(short = "o", long = "output", default_value = "book.txt")]
+    output: String,
+}
+
+fn main() {
+    let result = match Cli::from_args().command {
+        None => tournament(TournamentOptions::default()),
+        Some(Command::Tournament(options)) => tournament(options),
+        Some(Command::Tune(options)) => run_tune(options),
+        Some(Command::Book(options)) => run_book(options),
+    };
+    if let Err(why) = result {
+        eprintln!("{}", why);
+        std::process::exit(1);
     }
-    for g in games {
-        println!("{} vs {}: {:?}", (g.0).0, (g.0).1, g.1);
+}
+
+fn run_tune(options: TuneOptions) -> Result<(), String> {
+    let start = match options.weights {
+        Some(path) => Weights::load(&path)?,
+        None => Weights::diagonal2(),
+    };
+    let played = match options.load_games {
+        Some(path) => tune::load_games(&path)?,
+        None => {
+            println!("playing {} self-play games", options.games);
+            tune::self_play(start, options.games, options.depth, options.random_plies,
+                            options.seed)
+        },
+    };
+    if let Some(path) = options.save_games {
+        tune::save_games(&played, &path)?;
     }
-    println!("");
-    for s_idx in 0..scores.len() {
-        println!("{}: {}", ais[s_idx].0, scores[s_idx]);
+    let samples = tune::samples(&played)?;
+    println!("tuning on {} positions from {} games", samples.len(), played.len());
+    let tuned = tune::tune(&samples, start, options.step);
+    tuned.save(&options.output)?;
+    println!("wrote {}", options.output);
+    return Ok(());
+}
+
+fn run_book(options: BookOptions) -> Result<(), String> {
+    let records = match options.load_records {
+        Some(path) => GameRecord::load_all(&path)?,
+        None => {
+            println!("playing {} self-play games", options.games);
+            bookgen::self_play(options.games, options.depth, options.random_plies,
+                               options.seed)?
+        },
+    };
+    if let Some(path) = options.save_records {
+        bookgen::save_records(&records, &path)?;
     }
+    let lines = bookgen::build(&records, options.plies, options.min_games)?;
+    println!("{} lines from {} games", lines.len(), records.len());
+    bookgen::save_book(&lines, &options.output)?;
+    println!("wrote {}", options.output);
+    return Ok(());
 }
 
-fn play_game(x_ai: &mut dyn AI, o_ai: &mut dyn AI) -> Player {
-    let mut times_vec = Vec::new();
-    let mut now = Instant::now();
-    let mut last_move = x_ai.get_move(-1);
-    times_vec.push(now.elapsed().as_millis());
-    let mut board = Board::new(2);
-    loop {
-        if last_move == -1 {
-            println!("X forfeited");
-            board.winner = Player::O;
-            break;
-        }
-        if !board.make_move(last_move as usize) {
-            println!("X made an illegal move {}", last_move);
-            board.winner = Player::O;
-            break;
-        }
-        board.pretty_print();
-        println!("");
-        if board.winner != Player::NEITHER {
-            break;
-        }
-        last_move = o_ai.get_move(last_move);
-        if last_move == -1 {
-            println!("O forfeited");
-            board.winner = Player::X;
-            break;
-        }
-        if !board.make_move(last_move as usize) {
-            println!("O made an illegal move {}", last_move);
-            board.winner = Player::X;
-            break;
+fn tournament(options: TournamentOptions) -> Result<(), String> {
+    let mut specs = options.entrants.clone();
+    if specs.is_empty() {
+        specs = vec!["search".to_string(), "mcts".to_string()];
+    }
+    for name in options.candidate.iter().chain(&options.baseline) {
+        if !specs.contains(name) {
+            specs.push(name.clone());
         }
-        board.pretty_print();
-        println!("");
-        if board.winner != Player::NEITHER {
-            break;
+    }
+    let mut entrants = specs.iter()
+        .map(|s| Entrant::from_spec(s))
+        .collect::<Result<Vec<_>, Error>>()?;
+    for e in entrants.iter_mut() {
+        e.ponder = options.ponder.contains(&e.name);
+    }
+    // The candidate goes first and the baseline second
+    for (place, name) in [(0, &options.candidate), (1, &options.baseline)] {
+        if let Some(name) = name {
+            let i = entrants.iter().position(|e| e.name == *name)
+                .ok_or(format!("no AI named {}", name))?;
+            let entrant = entrants.remove(i);
+            entrants.insert(place, entrant);
         }
-        now = Instant::now();
-        last_move = x_ai.get_move(last_move);
-        times_vec.push(now.elapsed().as_millis());
     }
-    board.pretty_print();
-    println!("{:?}", board.move_history);
-    println!("{:?}", times_vec);
-    x_ai.cleanup();
-    o_ai.cleanup();
-    println!("{:?} wins", board.winner);
-    return board.winner;
+    if options.format == Format::Sprt {
+        entrants.truncate(2);
+    }
+    let completed = match (&options.records, options.resume) {
+        (None, true) => return Err("--resume needs --records".to_string()),
+        (Some(path), true) if std::path::Path::new(path).exists() =>
+            GameRecord::load_for_resume(path)?,
+        _ => Vec::new(),
+    };
+    let first_seed = options.seed.or_else(|| completed.first().map(|r| r.seed))
+        .unwrap_or_else(rand::random);
+    println!("first seed: {}", first_seed);
+    if !completed.is_empty() {
+        println!("resuming after {} games", completed.len());
+    }
+    let mut t = Tournament::new(entrants, first_seed);
+    t.format = options.format;
+    t.rounds = match (options.rounds, options.format) {
+        (Some(rounds), _) => rounds,
+        // The smallest number of rounds to have a single winner
+        (None, Format::Swiss) => (t.entrants.len().max(2) as f64).log2().ceil() as usize,
+        (None, Format::Sprt) => usize::MAX,
+        (None, _) => 1,
+    };
+    t.sprt = Sprt::new(options.elo0, options.elo1, options.alpha, options.beta)?;
+    t.completed = completed;
+    if let Some(path) = &options.openings {
+        t.openings = Book::load(path)?.lines.into_iter().map(|l| l.moves).collect();
+    }
+    t.adjudication = Adjudication {
+        win_score: options.adjudicate_score,
+        win_moves: options.adjudicate_moves,
+        draw: options.adjudicate_draws,
+    };
+    t.rules = options.rules;
+    t.records = options.records.clone();
+    t.json = options.json.clone();
+    let records = t.run()?;
+    for r in &records {
+        println!("{} vs {}: {:?}", r.x, r.o, r.winner);
+    }
+    println!();
+    print!("{}", standings_table(&t.standings(&records)));
+    return Ok(());
 }
@@ -0,0 +1,450 @@
+use crate::ai::AI;
+use crate::bitboard::BitBoard;
+use crate::error::Error;
+use crate::rules::Rules;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long the AI searches before making each move
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Budget {
+    // Run this many playouts per move
+    Iterations(u64),
+    // Run playouts until this much time has passed
+    Time(Duration),
+}
+
+// Picks the next move of a playout given the board
+pub type Playout = Box<dyn Fn(&mut BitBoard, &mut dyn RngCore) -> u128 + Send + Sync>;
+
+// A node of the search tree, stored in MctsAI::tree
+// and referred to by its index
+struct Node {
+    // The move that led from the parent to this node,
+    // or 0 for the root
+    mv: u128,
+    // 1 if X made mv, -1 if O made mv
+    player: i8,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Legal moves from this node that do not have a child yet
+    untried: u128,
+    visits: u64,
+    // Sum of playout results from the perspective of player,
+    // where a win is 1, a draw is 0.5 and a loss is 0
+    wins: f64,
+}
+
+impl Node {
+    fn new(board: &BitBoard, mv: u128, parent: Option<usize>) -> Node {
+        Node {
+            mv,
+            player: -board.to_move,
+            parent,
+            children: Vec::new(),
+            untried: board.get_moves(),
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+}
+
+pub struct MctsAI {
+    board: BitBoard,
+    tree: Vec<Node>,
+    playout: Playout,
+    exploration: f64,
+    budget: Budget,
+    // Number of threads running playouts on the shared tree
+    threads: usize,
+    // Each search thread gets its own generator seeded from this
+    // one. Searches with more than one thread are still not
+    // reproducible, since the threads race for the tree
+    rng: StdRng,
+    // Ends the search before the budget is used up, see AI::set_stop
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl AI for MctsAI {
+    fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+        if last_move != -1 {
+            if !(0..81).contains(&last_move) {
+                return Err(Error::OutOfRange { what: "space", value: last_move });
+            }
+            self.board.check_move(last_move as usize)
+                .map_err(|reason| Error::illegal_move(last_move as usize, reason))?;
+            self.board.make_move(1 << last_move);
+            self.advance(1 << last_move);
+        }
+        let iterations = self.parallel_search();
+        let best = match self.best_child() {
+            Some(best) => best,
+            None => return Err(Error::GameOver),
+        };
+        let node = &self.tree[best];
+        eprintln!("playouts: {} visits: {} win rate: {}",
+                  iterations, node.visits, node.wins / node.visits as f64);
+        let result_move = node.mv;
+        self.board.make_move(result_move);
+        self.advance(result_move);
+        return Ok(result_move.trailing_zeros() as i64);
+    }
+
+    fn cleanup(&mut self) {}
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) -> bool {
+        self.stop = Some(stop);
+        return true;
+    }
+
+    // The most visited reply in the tree kept for the next move
+    fn ponder_move(&self) -> Option<i64> {
+        return self.best_child().map(|c| self.tree[c].mv.trailing_zeros() as i64);
+    }
+
+    fn set_rules(&mut self, rules: Rules) -> bool {
+        if self.board.empty_spaces() != 81 {
+            return false;
+        }
+        self.board = BitBoard::with_rules(rules);
+        self.tree = vec![Node::new(&self.board, 0, None)];
+        return true;
+    }
+
+    fn set_opening(&mut self, moves: &[usize]) -> bool {
+        for m in moves {
+            if *m >= 81 || self.board.get_moves() & (1 << m) == 0 {
+                return false;
+            }
+            self.board.make_move(1 << m);
+        }
+        self.tree = vec![Node::new(&self.board, 0, None)];
+        return true;
+    }
+}
+
+impl MctsAI {
+    // Creates an AI that uses playout to pick moves during playouts,
+    // exploration as the UCT exploration constant and searches
+    // for budget before each move on threads threads
+    pub fn new(_playout: Playout, _exploration: f64, _budget: Budget,
+               _threads: usize) -> MctsAI {
+        let board = BitBoard::new();
+        MctsAI {
+            board,
+            tree: vec![Node::new(&board, 0, None)],
+            playout: _playout,
+            exploration: _exploration,
+            budget: _budget,
+            threads: _threads.max(1),
+            rng: StdRng::seed_from_u64(0),
+            stop: None,
+        }
+    }
+
+    // Run playouts on self.threads threads sharing one tree until
+    // the budget is used up, and return the number of playouts run
+    fn parallel_search(&mut self) -> u64 {
+        let tree = Mutex::new(std::mem::take(&mut self.tree));
+        let started = AtomicU64::new(0);
+        let finished = AtomicU64::new(0);
+        let now = Instant::now();
+        let seeds: Vec<u64> = (0..self.threads).map(|_| self.rng.gen()).collect();
+        let first_seed = self.rng.gen();
+        let this = &*self;
+        // However small the budget, expand the root so that there
+        // is a move to return. The playout counts towards the budget
+        if tree.lock().unwrap()[0].children.is_empty() && self.board.get_winner() == 0 {
+            started.fetch_add(1, Ordering::Relaxed);
+            this.iterate(&tree, &mut StdRng::seed_from_u64(first_seed));
+            finished.fetch_add(1, Ordering::Relaxed);
+        }
+        let work = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            loop {
+                let done = match this.budget {
+                    Budget::Iterations(n) => started.fetch_add(1, Ordering::Relaxed) >= n,
+                    Budget::Time(t) => now.elapsed() >= t,
+                } || this.stop.as_ref().is_some_and(|s| s.load(Ordering::Relaxed));
+                if done {
+                    break;
+                }
+                this.iterate(&tree, &mut rng);
+                finished.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+        thread::scope(|s| {
+            for seed in &seeds[1..] {
+                let seed = *seed;
+                s.spawn(move || work(seed));
+            }
+            work(seeds[0]);
+        });
+        self.tree = tree.into_inner().unwrap();
+        return finished.into_inner();
+    }
+
+    // Run one selection, expansion, playout and backpropagation
+    // step starting at the root
+    //
+    // The tree is only locked while selecting and while
+    // backpropagating, so threads run their playouts in parallel.
+    // Selection adds a visit to every node on the path before the
+    // playout's result is known (a virtual loss), which steers
+    // other threads away from the same path in the meantime
+    fn iterate(&self, shared_tree: &Mutex<Vec<Node>>, rng: &mut dyn RngCore) {
+        let mut board = self.board;
+        let mut tree = shared_tree.lock().unwrap();
+        let mut current = 0;
+        tree[current].visits += 1;
+        // Selection
+        while tree[current].untried == 0 {
+            current = match self.select_child(&tree, current) {
+                Some(child) => child,
+                None => break,
+            };
+            tree[current].visits += 1;
+            board.make_move(tree[current].mv);
+        }
+        // Expansion
+        if tree[current].untried != 0 {
+            let mv = BitBoard::random_move(tree[current].untried, rng);
+            tree[current].untried &= !mv;
+            board.make_move(mv);
+            let child = tree.len();
+            let mut node = Node::new(&board, mv, Some(current));
+            node.visits = 1;
+            tree.push(node);
+            tree[current].children.push(child);
+            current = child;
+        }
+        drop(tree);
+        // Playout
+        while board.get_winner() == 0 {
+            let mv = (self.playout)(&mut board, rng);
+            board.make_move(mv);
+        }
+        // Backpropagation, the visits were already counted
+        let winner = board.get_winner();
+        let mut tree = shared_tree.lock().unwrap();
+        let mut node = Some(current);
+        while let Some(i) = node {
+            let n = &mut tree[i];
+            if winner == n.player {
+                n.wins += 1.0;
+            } else if winner == -2 {
+                n.wins += 0.5;
+            }
+            node = n.parent;
+        }
+    }
+
+    // Returns the child of parent with the highest UCT value, or
+    // None if it has no children
+    fn select_child(&self, tree: &[Node], parent: usize) -> Option<usize> {
+        let log_visits = (tree[parent].visits as f64).ln();
+        let mut best = None;
+        let mut best_value = f64::NEG_INFINITY;
+        for &child in &tree[parent].children {
+            let n = &tree[child];
+            let value = n.wins / n.visits as f64
+                        + self.exploration * (log_visits / n.visits as f64).sqrt();
+            if best.is_none() || value > best_value {
+                best_value = value;
+                best = Some(child);
+            }
+        }
+        return best;
+    }
+
+    // Returns the most visited child of the root, or None if the
+    // root has no children because the game is over
+    fn best_child(&self) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for &child in &self.tree[0].children {
+            if best.is_none_or(|b| self.tree[child].visits > self.tree[b].visits) {
+                best = Some(child);
+            }
+        }
+        return best;
+    }
+
+    // Make the child of the root reached by mv the new root, keeping
+    // its subtree and discarding the rest of the tree. self.board must
+    // already have mv made on it
+    fn advance(&mut self, mv: u128) {
+        let new_root = self.tree[0].children.iter()
+            .cloned()
+            .find(|&c| self.tree[c].mv == mv);
+        let old_tree = std::mem::take(&mut self.tree);
+        match new_root {
+            None => self.tree.push(Node::new(&self.board, 0, None)),
+            Some(r) => {
+                let mut old_tree: Vec<Option<Node>> = old_tree.into_iter().map(Some).collect();
+                // (index in old_tree, index of parent in self.tree)
+                let mut stack = vec![(r, None)];
+                while let Some((old, parent)) = stack.pop() {
+                    let mut n = old_tree[old].take().unwrap();
+                    let idx = self.tree.len();
+                    for &c in &n.children {
+                        stack.push((c, Some(idx)));
+                    }
+                    n.children.clear();
+                    n.parent = parent;
+                    self.tree.push(n);
+                    if let Some(p) = parent {
+                        self.tree[p].children.push(idx);
+                    }
+                }
+                self.tree[0].mv = 0;
+            }
+        }
+    }
+
+    // Number of playouts that have passed through the root
+    pub fn root_visits(&self) -> u64 {
+        return self.tree[0].visits;
+    }
+
+    // Playout policy that picks uniformly random moves
+    pub fn random_playout() -> Playout {
+        Box::new(move |board: &mut BitBoard, rng: &mut dyn RngCore| -> u128 {
+            return BitBoard::random_move(board.get_moves(), rng);
+        })
+    }
+
+    // Playout policy that captures a small board whenever
+    // it can, and otherwise picks a random move
+    pub fn capture_playout() -> Playout {
+        Box::new(move |board: &mut BitBoard, rng: &mut dyn RngCore| -> u128 {
+            let moves = board.get_moves();
+            let mine = if board.to_move == 1 {
+                board.x_occupancy
+            } else {
+                board.o_occupancy
+            };
+            let mut capture = 0;
+            BitBoard::iterate_moves(moves, &mut |m: u128, sf: i64| {
+                let square = sf as usize / 9;
+                if BitBoard::has_line(((mine | m) >> (9 * square)) & 0x1ff) {
+                    capture = m;
+                    return false;
+                }
+                return true;
+            });
+            if capture != 0 {
+                return capture;
+            }
+            return BitBoard::random_move(moves, rng);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{IllegalReason, Player};
+
+     #[test]
+     fn test_takes_winning_move() {
+         // O can win the game by playing 23
+         let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29];
+         let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, Budget::Iterations(2000), 1);
+         for m in &moves[..moves.len() - 1] {
+             ai.board.make_move(1 << m);
+         }
+         ai.tree = vec![Node::new(&ai.board, 0, None)];
+         let m = ai.get_move(moves[moves.len() - 1]).unwrap();
+         assert_eq!(ai.board.get_winner(), -1);
+         let mut board = BitBoard::new();
+         for i in &moves {
+             board.make_move(1 << i);
+         }
+         board.make_move(1 << m);
+         assert_eq!(board.get_winner(), -1);
+     }
+
+     #[test]
+     fn test_tiny_budgets() {
+         // Too small a budget to run a playout still gives a legal move
+         for budget in [Budget::Iterations(0), Budget::Time(Duration::from_secs(0))] {
+             let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, budget, 2);
+             let m = ai.get_move(-1).unwrap();
+             assert!((0..81).contains(&m));
+             let reply = BitBoard::random_move(ai.board.get_moves(), &mut ai.rng);
+             assert!(ai.get_move(reply.trailing_zeros() as i64).is_ok());
+         }
+         // There is no move to make once the game is over
+         let moves = [0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23];
+         let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, Budget::Iterations(0), 1);
+         assert!(ai.set_opening(&moves));
+         assert_eq!(ai.get_move(-1), Err(Error::GameOver));
+     }
+
+     #[test]
+     fn test_bad_last_move() {
+         let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, Budget::Iterations(10), 1);
+         assert_eq!(ai.get_move(-5), Err(Error::OutOfRange { what: "space", value: -5 }));
+         assert_eq!(ai.get_move(81), Err(Error::OutOfRange { what: "space", value: 81 }));
+         let m = ai.get_move(40).unwrap();
+         let reason = IllegalReason::Occupied(Player::O);
+         assert_eq!(ai.get_move(m), Err(Error::IllegalMove { space: m as usize, reason }));
+     }
+
+     #[test]
+     fn test_tree_reuse() {
+         let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, Budget::Iterations(500), 1);
+         ai.get_move(-1).unwrap();
+         // The root is now the position after our move
+         // and keeps the playouts that went through it
+         assert!(ai.root_visits() > 0);
+         let reply = ai.tree[0].children[0];
+         let reply_move = ai.tree[reply].mv;
+         let reply_visits = ai.tree[reply].visits;
+         ai.board.make_move(reply_move);
+         ai.advance(reply_move);
+         assert_eq!(ai.root_visits(), reply_visits);
+         for (i, n) in ai.tree.iter().enumerate() {
+             for &c in &n.children {
+                 assert_eq!(ai.tree[c].parent, Some(i));
+             }
+         }
+     }
+
+     #[test]
+     fn test_parallel_visits() {
+         let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4, Budget::Iterations(2000), 4);
+         assert_eq!(ai.parallel_search(), 2000);
+         // Every virtual loss has been replaced by a real result
+         let mut child_visits = 0;
+         for &c in &ai.tree[0].children {
+             child_visits += ai.tree[c].visits;
+         }
+         assert_eq!(ai.root_visits(), 2000);
+         assert_eq!(child_visits, 2000);
+         for n in &ai.tree {
+             assert!(n.wins <= n.visits as f64);
+         }
+     }
+
+     #[test]
+     #[ignore]
+     fn test_playout_scaling() {
+         println!("Threads\tPlayouts/s");
+         for threads in [1, 2, 4, 8].iter() {
+             let mut ai = MctsAI::new(MctsAI::random_playout(), 1.4,
+                                      Budget::Time(Duration::from_secs(2)), *threads);
+             let playouts = ai.parallel_search();
+             println!("{}\t{}", threads, playouts / 2);
+         }
+     }
+}
@@ -0,0 +1,110 @@
+use crate::board::{Board, Square};
+use crate::error::Error;
+use crate::rules::Rules;
+
+// Names for spaces that people can read and type. A space is
+// named by its column, a to i from left to right, and its row,
+// 1 to 9 from top to bottom:
+//     a  b  c   d  e  f   g  h  i
+// 1  00 01 02  09 10 11  18 19 20
+// 2  03 04 05  12 13 14  21 22 23
+// 3  06 07 08  15 16 17  24 25 26
+//
+// 4  27 28 29  36 37 38  45 46 47
+// 5  30 31 32  39 40 41  48 49 50
+// 6  33 34 35  42 43 44  51 52 53
+//
+// 7  54 55 56  63 64 65  72 73 74
+// 8  57 58 59  66 67 68  75 76 77
+// 9  60 61 62  69 70 71  78 79 80
+// so the center space 40 is e5
+pub fn space_name(space: usize) -> String {
+    let (square, sub) = (space / 9, space % 9);
+    let column = (square % 3) * 3 + sub % 3;
+    let row = (square / 3) * 3 + sub / 3;
+    return format!("{}{}", (b'a' + column as u8) as char, row + 1);
+}
+
+// The name of a square: the space name for a space, and the
+// names of its top left and bottom right spaces for the others,
+// so the center small board is d4-f6
+pub fn square_name(sqr: Square) -> String {
+    if sqr.level == 0 {
+        return space_name(sqr.top_left);
+    }
+    let size = 9usize.pow(sqr.level as u32);
+    return format!("{}-{}", space_name(sqr.top_left), space_name(sqr.top_left + size - 1));
+}
+
+// Reads a space given by its name or by its number
+pub fn parse_space(text: &str) -> Result<usize, Error> {
+    let text = text.trim();
+    if let Ok(space) = text.parse::<usize>() {
+        if space < 81 {
+            return Ok(space);
+        }
+        return Err(Error::Parse(format!("bad space {}", text)));
+    }
+    let bytes = text.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'i').contains(&bytes[0].to_ascii_lowercase())
+       || !(b'1'..=b'9').contains(&bytes[1]) {
+        return Err(Error::Parse(format!("bad space {}", text)));
+    }
+    let column = (bytes[0].to_ascii_lowercase() - b'a') as usize;
+    let row = (bytes[1] - b'1') as usize;
+    return Ok(((row / 3) * 3 + column / 3) * 9 + (row % 3) * 3 + column % 3);
+}
+
+// The names of the moves of a game separated by spaces
+pub fn game_name(moves: &[usize]) -> String {
+    return moves.iter().map(|m| space_name(*m)).collect::<Vec<String>>().join(" ");
+}
+
+// Reads the moves of a game written by game_name, or as space
+// numbers, checking that they are legal under rules
+pub fn parse_game(text: &str, rules: Rules) -> Result<Vec<usize>, Error> {
+    let mut board = Board::new(2, rules)?;
+    let mut moves = Vec::new();
+    for word in text.split_whitespace() {
+        let m = parse_space(word)?;
+        if let Err(why) = board.make_move(m) {
+            return Err(Error::Parse(format!("{} after {} moves", why, moves.len())));
+        }
+        moves.push(m);
+    }
+    return Ok(moves);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_space_names() {
+         assert_eq!(space_name(0), "a1");
+         assert_eq!(space_name(40), "e5");
+         assert_eq!(space_name(20), "i1");
+         assert_eq!(space_name(60), "a9");
+         assert_eq!(space_name(80), "i9");
+         for space in 0..81 {
+             assert_eq!(parse_space(&space_name(space)), Ok(space));
+             assert_eq!(parse_space(&space.to_string()), Ok(space));
+         }
+         assert_eq!(parse_space("E5"), Ok(40));
+         assert!(parse_space("j1").is_err());
+         assert!(parse_space("a0").is_err());
+         assert!(parse_space("81").is_err());
+         assert_eq!(square_name(Square { top_left: 40, level: 0 }), "e5");
+         assert_eq!(square_name(Square { top_left: 36, level: 1 }), "d4-f6");
+         assert_eq!(square_name(Square { top_left: 0, level: 2 }), "a1-i9");
+     }
+
+     #[test]
+     fn test_games() {
+         let moves = vec![40, 36, 0];
+         assert_eq!(game_name(&moves), "e5 d4 a1");
+         assert_eq!(parse_game("e5 d4 a1", Rules::default()), Ok(moves.clone()));
+         assert_eq!(parse_game("40 d4 0", Rules::default()), Ok(moves));
+         assert!(parse_game("e5 e5", Rules::default()).is_err());
+     }
+}
@@ -1,53 +1,576 @@
 use crate::ai::AI;
+use crate::error::Error;
+use crate::rules::Rules;
 use std::process::{Command, Stdio, Child};
-use std::io::Write;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
+// How long an engine has to answer uttti before it is
+// treated as a legacy bot
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Commands, with their arguments, found to start legacy engines.
+// They are started in legacy mode straight away, rather than after
+// waiting for the handshake every game
+static LEGACY_COMMANDS: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+
+// How long an uttti engine has to answer isready
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Time each side starts the game with unless set_time is called
+const DEFAULT_TIME: Duration = Duration::from_secs(600);
+
+// How long after its time has run out an engine may still answer,
+// to allow for the time it takes to pass lines through the pipes
+const TIME_GRACE: Duration = Duration::from_secs(1);
+
+// An option an engine declared during the handshake
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct EngineOption {
+    pub name: String,
+    // spin, check, string or button
+    pub kind: String,
+    pub default: Option<String>,
+    // The range of a spin option
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl EngineOption {
+    // An integer option between min and max
+    pub fn spin(_name: &str, _default: i64, _min: i64, _max: i64) -> EngineOption {
+        EngineOption {
+            name: _name.to_string(),
+            kind: "spin".to_string(),
+            default: Some(_default.to_string()),
+            min: Some(_min),
+            max: Some(_max),
+        }
+    }
+
+    // A true or false option
+    pub fn check(_name: &str, _default: bool) -> EngineOption {
+        EngineOption {
+            name: _name.to_string(),
+            kind: "check".to_string(),
+            default: Some(_default.to_string()),
+            min: None,
+            max: None,
+        }
+    }
+
+    // An option whose value is any text
+    pub fn string(_name: &str, _default: &str) -> EngineOption {
+        EngineOption {
+            name: _name.to_string(),
+            kind: "string".to_string(),
+            default: Some(_default.to_string()),
+            min: None,
+            max: None,
+        }
+    }
+
+    // The value setting the option to text gives it, or None if the
+    // option can't take it. Spin values are clamped to the range
+    pub fn value(&self, text: &str) -> Option<String> {
+        return match self.kind.as_str() {
+            "spin" => text.parse::<i64>().ok().map(|v| {
+                let v = self.min.map_or(v, |min| v.max(min));
+                return self.max.map_or(v, |max| v.min(max)).to_string();
+            }),
+            "check" if text == "true" || text == "false" => Some(text.to_string()),
+            "check" => None,
+            _ => Some(text.to_string()),
+        };
+    }
+}
+
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Mode {
+    // The engine speaks uttti, see PROTOCOL.md
+    Uttti,
+    // The engine reads the opponent's move as a number followed
+    // by \r\n and answers with its move (-1 to start the game)
+    Legacy,
+}
+
+// Drives an engine running in another process over its stdin
+// and stdout, speaking uttti if the engine answers the
+// handshake and the legacy protocol otherwise
 pub struct PipeAI {
     process: Child,
+    // Lines read from the engine's stdout by a reader thread
+    lines: Receiver<String>,
+    mode: Mode,
+    name: String,
+    author: String,
+    options: Vec<EngineOption>,
+    // The moves of the game so far, sent with every go
+    moves: Vec<usize>,
+    // Remaining time of X and of O
+    time: [Duration; 2],
+    // When this engine last returned a move, so that the time
+    // until the next get_move can be charged to the opponent
+    last_returned: Option<Instant>,
+    // The score of the last info line before the last bestmove
+    last_score: Option<i32>,
+    // Whether to let the engine think on the opponent's time
+    ponder: bool,
+    // The reply the engine is pondering on, if it is
+    pondering: Option<usize>,
 }
 
 impl AI for PipeAI {
-    fn get_move(&mut self, last_move: i64) -> i64 {
-        let to_send = last_move.to_string() + "\r\n";
-        match self.process.stdin.as_mut().unwrap().write(to_send.as_bytes()) {
-            Err(why) => panic!("couldn't write to AI: {}", why),
-            Ok(_) => (),
-        }
-        self.process.stdin.as_mut().unwrap().flush().unwrap();
-        //println!("sent {} successfully", to_send);
-        let mut res_vec : Vec<u8> = vec![32; 100];
-        match self.process.stdout.as_mut().unwrap().read(&mut res_vec[..]) {
-            Err(_) => panic!("couldn't read from AI:"),
-            Ok(_) => (),
+    // Legacy engines aren't told the time, but both kinds lose
+    // with EngineTimeout once they use up their time
+    fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+        let to_move = self.moves.len() % 2;
+        if last_move != -1 {
+            self.moves.push(last_move as usize);
+            if let Some(t) = self.last_returned {
+                self.time[1 - to_move] = self.time[1 - to_move].saturating_sub(t.elapsed());
+            }
+        }
+        let to_move = self.moves.len() % 2;
+        let now = Instant::now();
+        let deadline = now + self.time[to_move] + TIME_GRACE;
+        self.last_score = None;
+        let (result, reply) = if self.mode == Mode::Legacy {
+            self.send(&(last_move.to_string() + "\r\n"))?;
+            (self.read_line(deadline)?.trim().parse::<i64>().unwrap_or(-1), None)
+        } else {
+            let pondered = self.pondering.take().map(|m| m as i64);
+            if pondered == Some(last_move) {
+                self.send("ponderhit\n")?;
+            } else {
+                if pondered.is_some() {
+                    // The move found for the wrong reply is thrown away
+                    self.send("stop\n")?;
+                    self.read_best_move(deadline)?;
+                    self.last_score = None;
+                }
+                self.go(&[], false)?;
+            }
+            self.read_best_move(deadline)?
         };
-        let untrimmed_response = String::from_utf8(res_vec).unwrap();
-        let response = untrimmed_response.trim();
-        //println!("received {} successfully", response);
-        return match response.parse::<i64>() {
-            Err(_) => -1,
-            Ok(t) => t,
+        self.time[to_move] = self.time[to_move].saturating_sub(now.elapsed());
+        if (0..81).contains(&result) {
+            self.moves.push(result as usize);
+            if let (true, Some(r)) = (self.ponder, reply) {
+                self.go(&[r], true)?;
+                self.pondering = Some(r);
+            }
         }
+        self.last_returned = Some(Instant::now());
+        return Ok(result);
     }
 
     fn cleanup(&mut self) {
-        unsafe {
-            self.process.kill();
+        if self.pondering.take().is_some() {
+            let _ = self.send("stop\n");
+        }
+        if self.mode == Mode::Uttti && self.send("quit\n").is_ok() {
+            // Give the engine a moment to exit by itself
+            let deadline = Instant::now() + Duration::from_millis(200);
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = self.process.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.process.kill();
+    }
+
+    // Engines that don't declare a Rules option only know
+    // the standard game
+    fn set_rules(&mut self, rules: Rules) -> bool {
+        if self.mode == Mode::Legacy || !self.options.iter().any(|o| o.name == "Rules") {
+            return rules == Rules::default();
         }
+        return self.set_option("Rules", &rules.to_string()).is_ok();
+    }
+
+    fn set_opening(&mut self, moves: &[usize]) -> bool {
+        if self.mode == Mode::Legacy {
+            return false;
+        }
+        self.moves = moves.to_vec();
+        return true;
+    }
+
+    // Only uttti engines are asked to ponder, on the reply
+    // they name with their bestmove
+    fn set_ponder(&mut self, enabled: bool) -> bool {
+        self.ponder = enabled && self.mode == Mode::Uttti;
+        return self.mode == Mode::Uttti;
+    }
+
+    fn last_score(&self) -> Option<i32> {
+        return self.last_score;
     }
 }
 
 impl PipeAI {
-    pub fn new(cmd: String, args: Vec<String>) -> PipeAI {
-        PipeAI {
-            process: match Command::new(cmd.clone())
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::piped())
-                            .args(&args[..])
-                            .spawn() {
-                Err(why) => panic!("couldn't spawn {}: {:?}", cmd, why),
-                Ok(process) => process,
+    // Starts the engine and performs the handshake. Engines that
+    // don't complete it are restarted in legacy mode
+    pub fn new(cmd: String, args: Vec<String>) -> Result<PipeAI, Error> {
+        let legacy = LEGACY_COMMANDS.lock().unwrap().iter().any(|(c, a)| *c == cmd && *a == args);
+        if legacy {
+            return PipeAI::spawn(&cmd, &args);
+        }
+        let mut ai = PipeAI::spawn(&cmd, &args)?;
+        ai.send("uttti\n")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let now = Instant::now();
+            let line = if now < deadline {
+                match ai.lines.recv_timeout(deadline - now) {
+                    Ok(line) => Some(line),
+                    Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+                }
+            } else {
+                None
+            };
+            match line.as_ref().map(|l| l.trim()) {
+                Some("uttti ok") => break,
+                Some(l) if l.starts_with("id ") || l.starts_with("option ") || l.is_empty() => {
+                    ai.handshake_line(l);
+                },
+                _ => {
+                    // Anything else means the engine doesn't know uttti,
+                    // and it may have been confused by the handshake
+                    let _ = ai.process.kill();
+                    LEGACY_COMMANDS.lock().unwrap().push((cmd.clone(), args.clone()));
+                    return PipeAI::spawn(&cmd, &args);
+                },
+            }
+        }
+        ai.mode = Mode::Uttti;
+        ai.send("newgame\n")?;
+        ai.send("isready\n")?;
+        let deadline = Instant::now() + READY_TIMEOUT;
+        while ai.read_line(deadline)?.trim() != "readyok" {}
+        return Ok(ai);
+    }
+
+    fn spawn(cmd: &str, args: &[String]) -> Result<PipeAI, Error> {
+        let mut process = match Command::new(cmd)
+                                    .stdin(Stdio::piped())
+                                    .stdout(Stdio::piped())
+                                    .args(args)
+                                    .spawn() {
+            Err(why) => return Err(Error::EngineSpawn { command: cmd.to_string(),
+                                                        reason: why.to_string() }),
+            Ok(process) => process,
+        };
+        let stdout = process.stdout.take().unwrap();
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(l) => if sender.send(l).is_err() {
+                        return;
+                    },
+                    Err(_) => return,
+                }
+            }
+        });
+        return Ok(PipeAI {
+            process,
+            lines: receiver,
+            mode: Mode::Legacy,
+            name: cmd.to_string(),
+            author: String::new(),
+            options: Vec::new(),
+            moves: Vec::new(),
+            time: [DEFAULT_TIME; 2],
+            last_returned: None,
+            last_score: None,
+            ponder: false,
+            pondering: None,
+        });
+    }
+
+    // Records an id or option line sent during the handshake
+    fn handshake_line(&mut self, line: &str) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["id", "name", rest @ ..] => self.name = rest.join(" "),
+            ["id", "author", rest @ ..] => self.author = rest.join(" "),
+            ["option", "name", rest @ ..] => {
+                let value_of = |key: &str| rest.iter().position(|w| *w == key)
+                    .and_then(|i| rest.get(i + 1))
+                    .map(|w| w.to_string());
+                let name_end = rest.iter().position(|w| *w == "type").unwrap_or(rest.len());
+                self.options.push(EngineOption {
+                    name: rest[..name_end].join(" "),
+                    kind: value_of("type").unwrap_or_default(),
+                    default: value_of("default"),
+                    min: value_of("min").and_then(|v| v.parse().ok()),
+                    max: value_of("max").and_then(|v| v.parse().ok()),
+                });
             },
+            _ => (),
         }
     }
-}
\ No newline at end of file
+
+    // Sends the position after the moves so far followed by extra,
+    // and starts a search of it, a ponder search if ponder is set
+    fn go(&mut self, extra: &[usize], ponder: bool) -> Result<(), Error> {
+        let mut position = String::from("position moves");
+        for m in self.moves.iter().chain(extra) {
+            position += &format!(" {}", m);
+        }
+        self.send(&(position + "\n"))?;
+        return self.send(&format!("go {}wtime {} btime {}\n", if ponder { "ponder " } else { "" },
+                                  self.time[0].as_millis(), self.time[1].as_millis()));
+    }
+
+    // Reads lines up to the engine's bestmove, recording the scores
+    // of info lines on the way. Returns the move, -1 to resign, and
+    // the reply the engine would like to ponder on
+    fn read_best_move(&mut self, deadline: Instant) -> Result<(i64, Option<usize>), Error> {
+        loop {
+            let line = self.read_line(deadline)?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["bestmove", m, rest @ ..] => {
+                    let space = |w: &str| w.parse::<usize>().ok().filter(|m| *m < 81);
+                    let reply = match rest {
+                        ["ponder", r, ..] => space(r),
+                        _ => None,
+                    };
+                    return Ok((space(m).map_or(-1, |m| m as i64), reply));
+                },
+                ["bestmove"] => return Ok((-1, None)),
+                ["info", rest @ ..] => self.info_line(rest),
+                _ => (),
+            }
+        }
+    }
+
+    // Records the score of an info line sent while thinking
+    fn info_line(&mut self, words: &[&str]) {
+        // Everything after pv is moves, which may
+        // include the word score
+        let end = words.iter().position(|w| *w == "pv").unwrap_or(words.len());
+        if let Some(i) = words[..end].iter().position(|w| *w == "score") {
+            if let Some(Ok(score)) = words.get(i + 1).map(|w| w.parse()) {
+                self.last_score = Some(score);
+            }
+        }
+    }
+
+    fn send(&mut self, text: &str) -> Result<(), Error> {
+        let stdin = self.process.stdin.as_mut().unwrap();
+        return stdin.write_all(text.as_bytes()).and_then(|_| stdin.flush())
+            .map_err(|why| Error::EngineIo { engine: self.name.clone(),
+                                             reason: why.to_string() });
+    }
+
+    // The next line the engine wrote, waiting for it until deadline
+    fn read_line(&mut self, deadline: Instant) -> Result<String, Error> {
+        let line = self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        return match line {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(Error::EngineTimeout {
+                engine: self.name.clone(),
+            }),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::EngineIo {
+                engine: self.name.clone(),
+                reason: "it closed its output".to_string(),
+            }),
+        };
+    }
+
+    pub fn mode(&self) -> Mode {
+        return self.mode;
+    }
+
+    // The name the engine gave, or its command if it gave none
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn author(&self) -> &str {
+        return &self.author;
+    }
+
+    pub fn options(&self) -> &[EngineOption] {
+        return &self.options;
+    }
+
+    // Sets one of the engine's options. Legacy engines have none
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if self.mode == Mode::Legacy || !self.options.iter().any(|o| o.name == name) {
+            return Err(Error::Unsupported { ai: self.name.clone(),
+                                            what: format!("set option {}", name) });
+        }
+        return self.send(&format!("setoption name {} value {}\n", name, value));
+    }
+
+    // Sets the time each side starts the game with
+    pub fn set_time(&mut self, time: Duration) {
+        self.time = [time; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     // A shell script engine that answers the handshake and
+     // always plays the lowest space not in the position
+     const UTTTI_ENGINE: &str = r#"
+         while read cmd rest; do
+             case $cmd in
+                 uttti) echo "id name scripted"; echo "id author tests";
+                        echo "option name Depth type spin default 3 min 1 max 9";
+                        echo "uttti ok" ;;
+                 isready) echo readyok ;;
+                 position) pos="$rest" ;;
+                 go) m=0
+                     for w in $pos; do
+                         if [ "$w" = "$m" ]; then m=$((m + 1)); fi
+                     done
+                     echo "info depth 1 score -$m pv $m"; echo "bestmove $m" ;;
+                 quit) exit 0 ;;
+             esac
+         done"#;
+
+     // An uttti engine like UTTTI_ENGINE that asks to ponder on
+     // the space after its move, and holds back the bestmove of a
+     // ponder search until ponderhit or stop
+     const PONDER_ENGINE: &str = r#"
+         while read cmd rest; do
+             case $cmd in
+                 uttti) echo "uttti ok" ;;
+                 isready) echo readyok ;;
+                 position) pos="$rest" ;;
+                 go) m=0
+                     for w in $pos; do
+                         if [ "$w" = "$m" ]; then m=$((m + 1)); fi
+                     done
+                     case $rest in
+                         ponder*) held=$m ;;
+                         *) echo "bestmove $m ponder $((m + 1))" ;;
+                     esac ;;
+                 ponderhit|stop) echo "bestmove $held ponder $((held + 1))" ;;
+                 quit) exit 0 ;;
+             esac
+         done"#;
+
+     // A legacy engine that answers every line with 40
+     const LEGACY_ENGINE: &str = "while read line; do echo 40; done";
+
+     // An uttti engine that does what go_command says when
+     // asked for a move
+     fn failing_engine(go_command: &str) -> String {
+         return format!(r#"
+             while read cmd rest; do
+                 case $cmd in
+                     uttti) echo "uttti ok" ;;
+                     isready) echo readyok ;;
+                     go) {} ;;
+                 esac
+             done"#, go_command);
+     }
+
+     fn script(text: &str) -> PipeAI {
+         return PipeAI::new("sh".to_string(), vec!["-c".to_string(), text.to_string()]).unwrap();
+     }
+
+     #[test]
+     fn test_handshake() {
+         let mut ai = script(UTTTI_ENGINE);
+         assert_eq!(ai.mode(), Mode::Uttti);
+         assert_eq!(ai.name(), "scripted");
+         assert_eq!(ai.author(), "tests");
+         assert_eq!(ai.options(), &[EngineOption::spin("Depth", 3, 1, 9)]);
+         assert_eq!(ai.set_option("Depth", "5"), Ok(()));
+         assert!(ai.set_option("Hash", "5").is_err());
+         assert!(!ai.set_rules("any-board".parse().unwrap()));
+         assert!(ai.set_rules(Rules::default()));
+         assert_eq!(ai.get_move(-1), Ok(0));
+         assert_eq!(ai.get_move(1), Ok(2));
+         assert_eq!(ai.last_score(), Some(-2));
+         assert!(ai.set_opening(&[]));
+         ai.cleanup();
+     }
+
+     #[test]
+     fn test_ponder() {
+         // A hit answers with the ponder search's move, a miss
+         // throws it away and searches the actual position
+         let mut ai = script(PONDER_ENGINE);
+         assert!(ai.set_ponder(true));
+         assert_eq!(ai.get_move(-1), Ok(0));
+         assert_eq!(ai.get_move(1), Ok(2));
+         assert_eq!(ai.get_move(5), Ok(3));
+         ai.cleanup();
+     }
+
+     #[test]
+     fn test_option_values() {
+         let depth = EngineOption::spin("Depth", 3, 1, 9);
+         assert_eq!(depth.value("5"), Some("5".to_string()));
+         assert_eq!(depth.value("0"), Some("1".to_string()));
+         assert_eq!(depth.value("100000000000"), Some("9".to_string()));
+         assert_eq!(depth.value("deep"), None);
+         let ponder = EngineOption::check("Ponder", false);
+         assert_eq!(ponder.value("true"), Some("true".to_string()));
+         assert_eq!(ponder.value("yes"), None);
+         assert_eq!(EngineOption::string("Name", "").value("a b"), Some("a b".to_string()));
+     }
+
+     #[test]
+     fn test_legacy_fallback() {
+         let mut ai = script(LEGACY_ENGINE);
+         assert_eq!(ai.mode(), Mode::Legacy);
+         assert!(!ai.set_ponder(true));
+         assert!(!ai.set_opening(&[40]));
+         assert!(!ai.set_rules("dead-boards".parse().unwrap()));
+         assert_eq!(ai.get_move(-1), Ok(40));
+         assert_eq!(ai.last_score(), None);
+         ai.cleanup();
+     }
+
+     #[test]
+     fn test_legacy_remembered() {
+         // An engine that is silent until it is sent a move is only
+         // waited for once
+         let silent = "while read line; do case $line in uttti) ;; *) echo 40 ;; esac; done";
+         let now = Instant::now();
+         let mut ai = script(silent);
+         assert_eq!(ai.mode(), Mode::Legacy);
+         assert!(now.elapsed() >= HANDSHAKE_TIMEOUT);
+         ai.cleanup();
+         let now = Instant::now();
+         let mut ai = script(silent);
+         assert_eq!(ai.mode(), Mode::Legacy);
+         assert!(now.elapsed() < HANDSHAKE_TIMEOUT);
+         assert_eq!(ai.get_move(-1), Ok(40));
+         ai.cleanup();
+     }
+
+     #[test]
+     fn test_failures() {
+         assert!(matches!(PipeAI::new("/nonexistent/engine".to_string(), vec![]),
+                          Err(Error::EngineSpawn { .. })));
+         let mut ai = script(&failing_engine("exit 1"));
+         assert!(matches!(ai.get_move(-1), Err(Error::EngineIo { .. })));
+         ai.cleanup();
+         let mut ai = script(&failing_engine("sleep 5"));
+         ai.set_time(Duration::from_millis(100));
+         assert!(matches!(ai.get_move(-1), Err(Error::EngineTimeout { .. })));
+         ai.cleanup();
+     }
+}
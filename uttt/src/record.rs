@@ -0,0 +1,346 @@
+use crate::board::Player;
+use crate::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::str::FromStr;
+
+// Why a game ended
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Termination {
+    // A line was completed or the board filled up
+    Normal,
+    // An AI resigned or stopped answering
+    Forfeit,
+    IllegalMove,
+    // An AI failed, for example an engine that crashed or
+    // ran out of time, or it couldn't be started
+    EngineError,
+    // Ended early by the game runner, see adjudication.rs
+    AdjudicatedWin,
+    AdjudicatedDraw,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Termination::Normal => "normal",
+            Termination::Forfeit => "forfeit",
+            Termination::IllegalMove => "illegal-move",
+            Termination::EngineError => "engine-error",
+            Termination::AdjudicatedWin => "adjudicated-win",
+            Termination::AdjudicatedDraw => "adjudicated-draw",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+impl FromStr for Termination {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Termination, Error> {
+        return match s {
+            "normal" => Ok(Termination::Normal),
+            "forfeit" => Ok(Termination::Forfeit),
+            "illegal-move" => Ok(Termination::IllegalMove),
+            "engine-error" => Ok(Termination::EngineError),
+            "adjudicated-win" => Ok(Termination::AdjudicatedWin),
+            "adjudicated-draw" => Ok(Termination::AdjudicatedDraw),
+            _ => Err(Error::Parse(format!("bad termination {}", s))),
+        };
+    }
+}
+
+// Everything needed to replay a game between two AIs: their
+// names, the seed both were seeded from, the opening they
+// started from and the moves played
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRecord {
+    pub x: String,
+    pub o: String,
+    pub seed: u64,
+    // Number of moves at the start of moves that were given
+    // as the opening rather than played by the AIs
+    pub opening: usize,
+    pub moves: Vec<usize>,
+    // Milliseconds taken by each move after the opening
+    pub times: Vec<u128>,
+    // The score each AI reported for its moves after the opening
+    // (see AI::last_score), from the mover's side
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub scores: Vec<Option<i32>>,
+    pub winner: Player,
+    pub termination: Termination,
+}
+
+impl GameRecord {
+    pub fn new(_x: &str, _o: &str, _seed: u64) -> GameRecord {
+        GameRecord {
+            x: _x.to_string(),
+            o: _o.to_string(),
+            seed: _seed,
+            opening: 0,
+            moves: Vec::new(),
+            times: Vec::new(),
+            scores: Vec::new(),
+            winner: Player::NEITHER,
+            termination: Termination::Normal,
+        }
+    }
+
+    // The seeds the X and O AIs of a game with the given
+    // seed are seeded with
+    pub fn ai_seeds(seed: u64) -> (u64, u64) {
+        // splitmix64 finalizer, so that games with neighbouring
+        // seeds do not share AI seeds
+        let mix = |v: u64| {
+            let mut h = v.wrapping_add(0x9e3779b97f4a7c15);
+            h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+            return h ^ (h >> 31);
+        };
+        return (mix(seed.wrapping_mul(2)), mix(seed.wrapping_mul(2) + 1));
+    }
+
+    // Appends the record to the file at path, creating it if needed.
+    // The record is written in one call and synced, so that a crash
+    // leaves at most the last record of the file cut short
+    pub fn append(&self, path: &str) -> Result<(), Error> {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| Error::Io(format!("couldn't open {}: {}", path, e)))?;
+        let text = format!("{}\n", self);
+        return file.write_all(text.as_bytes()).and_then(|_| file.sync_data())
+            .map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+    }
+
+    // Reads every record in a file written by append. Every record
+    // ends with a blank line, so text after the last one is a record
+    // that was cut short while being written, and is left out
+    pub fn load_all(path: &str) -> Result<Vec<GameRecord>, Error> {
+        let (records, _complete) = GameRecord::load_complete(path)?;
+        return Ok(records);
+    }
+
+    // Reads the records of a file as load_all does and cuts the file
+    // back to the end of the last complete one, so that the next
+    // append doesn't continue a record that was cut short
+    pub fn load_for_resume(path: &str) -> Result<Vec<GameRecord>, Error> {
+        let (records, complete) = GameRecord::load_complete(path)?;
+        let file = fs::OpenOptions::new().write(true).open(path)
+            .map_err(|e| Error::Io(format!("couldn't open {}: {}", path, e)))?;
+        file.set_len(complete as u64).and_then(|_| file.sync_data())
+            .map_err(|e| Error::Io(format!("couldn't truncate {}: {}", path, e)))?;
+        return Ok(records);
+    }
+
+    // The complete records of the file at path and the number of
+    // bytes they take up
+    fn load_complete(path: &str) -> Result<(Vec<GameRecord>, usize), Error> {
+        let bytes = fs::read(path)
+            .map_err(|e| Error::Io(format!("couldn't read {}: {}", path, e)))?;
+        let complete = match bytes.windows(2).rposition(|w| w == b"\n\n") {
+            Some(i) => i + 2,
+            None => 0,
+        };
+        if bytes[complete..].iter().any(|b| !b.is_ascii_whitespace()) {
+            eprintln!("{}: leaving out a record that was cut short", path);
+        }
+        let text = std::str::from_utf8(&bytes[..complete])
+            .map_err(|e| Error::Parse(format!("{} isn't text: {}", path, e)))?;
+        let mut records = Vec::new();
+        for block in text.split("\n\n").filter(|b| !b.trim().is_empty()) {
+            records.push(block.parse()?);
+        }
+        return Ok((records, complete));
+    }
+}
+
+// One "key value..." line per field:
+// x <name>
+// o <name>
+// seed <seed>
+// opening <number of opening moves>
+// moves <space> <space> ...
+// times <ms> <ms> ...
+// scores <score>|- <score>|- ...
+// winner X|O|D|-
+// termination normal|forfeit|illegal-move|engine-error|adjudicated-win|adjudicated-draw
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "x {}", self.x)?;
+        writeln!(f, "o {}", self.o)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "opening {}", self.opening)?;
+        write!(f, "moves")?;
+        for m in &self.moves {
+            write!(f, " {}", m)?;
+        }
+        writeln!(f)?;
+        write!(f, "times")?;
+        for t in &self.times {
+            write!(f, " {}", t)?;
+        }
+        writeln!(f)?;
+        write!(f, "scores")?;
+        for s in &self.scores {
+            match s {
+                Some(score) => write!(f, " {}", score)?,
+                None => write!(f, " -")?,
+            }
+        }
+        writeln!(f)?;
+        let winner = match self.winner {
+            Player::X => "X",
+            Player::O => "O",
+            Player::DEAD => "D",
+            Player::NEITHER => "-",
+        };
+        writeln!(f, "winner {}", winner)?;
+        return writeln!(f, "termination {}", self.termination);
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<GameRecord, Error> {
+        let mut record = GameRecord::new("", "", 0);
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            match key {
+                "x" => record.x = value.to_string(),
+                "o" => record.o = value.to_string(),
+                "seed" => record.seed = value.parse()
+                    .map_err(|_| Error::Parse(format!("bad seed {}", value)))?,
+                "opening" => record.opening = value.parse()
+                    .map_err(|_| Error::Parse(format!("bad opening length {}", value)))?,
+                "moves" => for w in value.split_whitespace() {
+                    match w.parse::<usize>() {
+                        Ok(m) if m < 81 => record.moves.push(m),
+                        _ => return Err(Error::Parse(format!("bad move {}", w))),
+                    }
+                },
+                "times" => for w in value.split_whitespace() {
+                    record.times.push(w.parse()
+                        .map_err(|_| Error::Parse(format!("bad time {}", w)))?);
+                },
+                "scores" => for w in value.split_whitespace() {
+                    record.scores.push(match w {
+                        "-" => None,
+                        _ => Some(w.parse()
+                            .map_err(|_| Error::Parse(format!("bad score {}", w)))?),
+                    });
+                },
+                "winner" => record.winner = match value {
+                    "X" => Player::X,
+                    "O" => Player::O,
+                    "D" => Player::DEAD,
+                    "-" => Player::NEITHER,
+                    _ => return Err(Error::Parse(format!("bad winner {}", value))),
+                },
+                "termination" => record.termination = value.parse()?,
+                _ => return Err(Error::Parse(format!("unknown field {}", key))),
+            }
+        }
+        return Ok(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_record_round_trip() {
+         let mut record = GameRecord::new("diagonal2", "mcts", 12345);
+         record.opening = 1;
+         record.moves = vec![40, 36, 4];
+         record.times = vec![250, 7];
+         record.scores = vec![Some(-30), None];
+         record.winner = Player::DEAD;
+         record.termination = Termination::AdjudicatedDraw;
+         assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record.clone()));
+         let path = std::env::temp_dir().join("uttt_test_records.txt");
+         let path = path.to_str().unwrap();
+         let _ = fs::remove_file(path);
+         record.append(path).unwrap();
+         record.seed = 6789;
+         record.append(path).unwrap();
+         let loaded = GameRecord::load_all(path).unwrap();
+         assert_eq!(loaded.len(), 2);
+         assert_eq!(loaded[0].seed, 12345);
+         assert_eq!(loaded[1], record);
+         // A record cut short by a crash is left out
+         let text = record.to_string();
+         let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+         file.write_all(&text.as_bytes()[..text.find("winner").unwrap()]).unwrap();
+         assert_eq!(GameRecord::load_all(path).unwrap(), loaded);
+         let _ = fs::remove_file(path);
+         assert!("seed x".parse::<GameRecord>().is_err());
+         assert!("termination resigned".parse::<GameRecord>().is_err());
+         assert!("scores 1 x".parse::<GameRecord>().is_err());
+         // Records written before terminations were recorded
+         // ended normally or by forfeit, read them as normal
+         assert_eq!("x a\no b\nwinner X".parse::<GameRecord>().unwrap().termination,
+                    Termination::Normal);
+     }
+
+     #[test]
+     fn test_resume_after_crash() {
+         // Whichever byte a crash stops the writes at, resuming keeps
+         // the records before it and the next record is read back
+         let mut first = GameRecord::new("a", "b", 1);
+         first.moves = vec![40, 36];
+         first.times = vec![5, 6];
+         first.scores = vec![Some(3), None];
+         first.winner = Player::X;
+         let mut second = first.clone();
+         second.seed = 2;
+         second.termination = Termination::AdjudicatedWin;
+         let mut third = first.clone();
+         third.seed = 3;
+         let text = format!("{}\n{}\n", first, second);
+         let path = std::env::temp_dir().join("uttt_test_resume.txt");
+         let path = path.to_str().unwrap();
+         for cut in 0..=text.len() {
+             fs::write(path, &text[..cut]).unwrap();
+             let mut expected: Vec<GameRecord> = [&first, &second].iter()
+                 .take(text[..cut].matches("\n\n").count()).map(|r| (*r).clone()).collect();
+             assert_eq!(GameRecord::load_for_resume(path).unwrap(), expected, "cut at {}", cut);
+             third.append(path).unwrap();
+             expected.push(third.clone());
+             assert_eq!(GameRecord::load_all(path).unwrap(), expected, "cut at {}", cut);
+         }
+         fs::remove_file(path).unwrap();
+     }
+
+     #[cfg(feature = "serde")]
+     #[test]
+     fn test_record_json() {
+         let mut record = GameRecord::new("diagonal2", "mcts", 12345);
+         record.moves = vec![40, 36];
+         record.times = vec![250, 7];
+         record.scores = vec![Some(12), None];
+         record.winner = Player::O;
+         record.termination = Termination::IllegalMove;
+         let json = serde_json::to_string(&record).unwrap();
+         assert_eq!(json, "{\"x\":\"diagonal2\",\"o\":\"mcts\",\"seed\":12345,\"opening\":0,\
+                           \"moves\":[40,36],\"times\":[250,7],\"scores\":[12,null],\
+                           \"winner\":\"O\",\"termination\":\"illegal-move\"}");
+         assert_eq!(serde_json::from_str::<GameRecord>(&json).unwrap(), record);
+     }
+}
@@ -0,0 +1,146 @@
+// The rule variants a Board can be played under. Rules::default()
+// is the standard game
+//
+// As text, for example on the command line, rules are written as
+// "standard" or as a comma separated list of the variants that
+// differ from it: most-squares, drawn-for-both, any-board and
+// dead-boards
+
+use crate::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// What happens when the big board fills up without a line
+#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum TieBreak {
+    // The game is drawn
+    Draw,
+    // The player who captured more small boards wins,
+    // and the game is drawn if both captured as many
+    MostSquares,
+}
+
+// Who a drawn small board counts for when looking for a line
+// of small boards
+#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum DrawnSquares {
+    Nobody,
+    // Both players, so a move that draws a small board can complete
+    // a line for either of them. If it completes one for both, the
+    // player who made it wins
+    Both,
+}
+
+// Where a player may move when sent to a small board that is
+// won or drawn
+#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum FreeMove {
+    // Any empty space in a small board that is still open
+    OpenBoards,
+    // Any empty space, including those in won (or dead) small boards.
+    // Moves in a closed small board don't change its owner
+    AnyBoard,
+}
+
+#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rules {
+    pub tie_break: TieBreak,
+    pub drawn_squares: DrawnSquares,
+    pub free_move: FreeMove,
+    // Whether a square that neither player can win any more is
+    // closed as soon as it dies rather than once it is full.
+    // No more moves can be made in it and a move that would send
+    // the opponent there gives them a free move. The big board
+    // dying draws the game under TieBreak::Draw. Under MostSquares
+    // the game goes on, since the remaining small boards still count
+    pub dead_boards: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            tie_break: TieBreak::Draw,
+            drawn_squares: DrawnSquares::Nobody,
+            free_move: FreeMove::OpenBoards,
+            dead_boards: false,
+        }
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut variants = Vec::new();
+        if self.tie_break == TieBreak::MostSquares {
+            variants.push("most-squares");
+        }
+        if self.drawn_squares == DrawnSquares::Both {
+            variants.push("drawn-for-both");
+        }
+        if self.free_move == FreeMove::AnyBoard {
+            variants.push("any-board");
+        }
+        if self.dead_boards {
+            variants.push("dead-boards");
+        }
+        if variants.is_empty() {
+            variants.push("standard");
+        }
+        return write!(f, "{}", variants.join(","));
+    }
+}
+
+impl FromStr for Rules {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rules, Error> {
+        let mut rules = Rules::default();
+        for variant in s.split(',').map(|v| v.trim()) {
+            match variant {
+                "standard" => (),
+                "most-squares" => rules.tie_break = TieBreak::MostSquares,
+                "drawn-for-both" => rules.drawn_squares = DrawnSquares::Both,
+                "any-board" => rules.free_move = FreeMove::AnyBoard,
+                "dead-boards" => rules.dead_boards = true,
+                _ => return Err(Error::Parse(format!("bad rule variant {}", variant))),
+            }
+        }
+        return Ok(rules);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_text() {
+         assert_eq!("standard".parse::<Rules>(), Ok(Rules::default()));
+         assert_eq!(Rules::default().to_string(), "standard");
+         let rules: Rules = "dead-boards, most-squares".parse().unwrap();
+         assert!(rules.dead_boards && rules.tie_break == TieBreak::MostSquares);
+         assert_eq!(rules.to_string(), "most-squares,dead-boards");
+         let all: Rules = "most-squares,drawn-for-both,any-board,dead-boards".parse().unwrap();
+         assert_eq!(all.to_string().parse::<Rules>(), Ok(all));
+         assert!("".parse::<Rules>().is_err());
+         assert!("standard,no-lines".parse::<Rules>().is_err());
+     }
+}
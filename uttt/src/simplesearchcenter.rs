@@ -1,79 +1,391 @@
 use crate::ai::AI;
 use crate::bitboard::BitBoard;
+use crate::book::Book;
+use crate::transposition::{TranspositionTable, TableEntry, Bound};
+use crate::solver::{Outcome, Solver};
+use crate::eval::{Evaluator, LinearEvaluator, Weights};
+use crate::error::Error;
+use crate::rules::Rules;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// The parts of the AI shared by its search threads, including
+// the ponder thread, which keeps running after get_move returns
+struct Searcher {
+    eval: Box<dyn Evaluator>,
+    table: TranspositionTable,
+    // Set once the main thread has finished searching so that
+    // the helper threads stop, or to stop pondering
+    stop: AtomicBool,
+    // Set from outside to stop the search early, see AI::set_stop
+    interrupt: Option<Arc<AtomicBool>>,
+}
 
 pub struct SimpleSearchCenterAI {
     board: BitBoard,
-    eval: Box<dyn Fn(&mut BitBoard, i8) -> i32>,
+    searcher: Arc<Searcher>,
     depth: usize,
-    me: i8,
+    // Number of threads searching each move. Helper threads
+    // run the same search as the main thread (Lazy SMP) and
+    // only contribute through the shared table
+    threads: usize,
+    // Once this few spaces are empty, the position is solved
+    // exactly instead of searched to depth
+    solve_below: u32,
+    solver: Solver,
+    // Source of the evaluator's noise and of the choice between
+    // book moves. Each search thread gets its own generator
+    // seeded from this one
+    rng: StdRng,
+    // Moves are taken from the book while it has one for history
+    book: Book,
+    // The moves made so far, including the opening
+    history: Vec<usize>,
+    // Whether to keep searching the expected reply after
+    // returning a move
+    ponder: bool,
+    // The reply the ponder thread expects and the thread itself
+    pondering: Option<(i64, thread::JoinHandle<()>)>,
+    // The score of the last move returned, None for book moves
+    last_score: Option<i32>,
 }
 
 impl AI for SimpleSearchCenterAI {
 
-    fn get_move(&mut self, last_move: i64) -> i64 {
+    fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+        if let Some(expected) = self.stop_pondering() {
+            // On a hit the search below finds the ponder search's
+            // results in the table, on a miss it starts over
+            eprintln!("ponder {}", if expected == last_move { "hit" } else { "miss" });
+        }
         if last_move != -1 {
+            if !(0..81).contains(&last_move) {
+                return Err(Error::OutOfRange { what: "space", value: last_move });
+            }
+            self.board.check_move(last_move as usize)
+                .map_err(|reason| Error::illegal_move(last_move as usize, reason))?;
             self.board.make_move(1 << last_move);
-        } else {
-            self.board.make_move(1 << 40);
-            return 40;
+            self.history.push(last_move as usize);
         }
-        self.me = self.board.to_move;
-        let alpha = -100000000;
-        let beta = 100000000;
-        let (result_move, result_score) = self.search(&mut self.board.clone(), self.depth, alpha, beta);
-        println!("result score: {}", result_score);
+        let (result_move, result_score) = match self.book.lookup(&self.history, &mut self.rng) {
+            Some(m) if self.board.get_moves() & (1 << m) != 0 => {
+                eprintln!("book move: {}", m);
+                (m as i64, None)
+            },
+            _ if self.board.empty_spaces() <= self.solve_below => {
+                let (result_move, outcome) = self.solver.solve(&self.board);
+                eprintln!("solved: {} ({} nodes)", outcome, self.solver.nodes);
+                // Reported on the scale of the evaluators, which
+                // score a won position 50000
+                let score = match outcome {
+                    Outcome::Win(_) => 50000,
+                    Outcome::Loss(_) => -50000,
+                    Outcome::Draw => 0,
+                };
+                (result_move, Some(score))
+            },
+            _ => {
+                let (mut result_move, result_score) = self.parallel_search();
+                if result_move == -1 && self.board.get_moves() != 0 {
+                    // Stopped before the first iteration finished
+                    result_move = self.board.get_moves().trailing_zeros() as i64;
+                }
+                eprintln!("result score: {}", result_score);
+                (result_move, Some(result_score))
+            },
+        };
+        // The searches find no move once the game is over
+        if result_move == -1 {
+            return Err(Error::GameOver);
+        }
+        self.last_score = result_score;
         self.board.make_move(1 << result_move);
-        return result_move;
+        self.history.push(result_move as usize);
+        if self.ponder {
+            self.start_pondering();
+        }
+        return Ok(result_move);
+    }
+
+    fn cleanup(&mut self) {
+        self.stop_pondering();
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn set_rules(&mut self, rules: Rules) -> bool {
+        if !self.history.is_empty() {
+            return false;
+        }
+        self.board = BitBoard::with_rules(rules);
+        if rules != Rules::default() {
+            // Books are made from games under the standard rules
+            self.book = Book::new();
+        }
+        return true;
     }
 
-    fn cleanup(&mut self) {}
+    fn set_opening(&mut self, moves: &[usize]) -> bool {
+        for m in moves {
+            if *m >= 81 || self.board.get_moves() & (1 << m) == 0 {
+                return false;
+            }
+            self.board.make_move(1 << m);
+            self.history.push(*m);
+        }
+        return true;
+    }
+
+    fn set_ponder(&mut self, enabled: bool) -> bool {
+        self.ponder = enabled;
+        if !enabled {
+            self.stop_pondering();
+        }
+        return true;
+    }
+
+    // The searcher can only be changed while no ponder
+    // thread shares it
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) -> bool {
+        return match Arc::get_mut(&mut self.searcher) {
+            Some(searcher) => {
+                searcher.interrupt = Some(stop);
+                true
+            },
+            None => false,
+        };
+    }
+
+    fn ponder_move(&self) -> Option<i64> {
+        return self.expected_reply();
+    }
+
+    fn last_score(&self) -> Option<i32> {
+        return self.last_score;
+    }
+}
+
+impl Drop for SimpleSearchCenterAI {
+    fn drop(&mut self) {
+        self.stop_pondering();
+    }
 }
 
 impl SimpleSearchCenterAI {
-    pub fn new<'a>(_eval: Box<dyn Fn(&mut BitBoard, i8) -> i32>, _depth: usize) 
-        -> SimpleSearchCenterAI {
+    pub fn new(_eval: Box<dyn Evaluator>, _depth: usize,
+               _threads: usize, _solve_below: u32) -> SimpleSearchCenterAI {
         SimpleSearchCenterAI {
             board: BitBoard::new(),
-            eval: _eval,
-            depth: _depth,
-            me: 0,
+            searcher: Arc::new(Searcher {
+                eval: _eval,
+                table: TranspositionTable::new(20),
+                stop: AtomicBool::new(false),
+                interrupt: None,
+            }),
+            depth: _depth.max(1),
+            threads: _threads.max(1),
+            solve_below: _solve_below,
+            solver: Solver::new(),
+            rng: StdRng::seed_from_u64(0),
+            book: Book::center(),
+            history: Vec::new(),
+            ponder: false,
+            pondering: None,
+            last_score: None,
+        }
+    }
+
+    // Replaces the default book, which always opens in the center
+    pub fn set_book(&mut self, _book: Book) {
+        self.book = _book;
+    }
+
+    // Search the current position with self.threads threads and
+    // return the main thread's best move and its score. The result
+    // only depends on the seed when a single thread searches, since
+    // helpers change what the main thread finds in the table
+    fn parallel_search(&mut self) -> (i64, i32) {
+        self.searcher.stop.store(false, Ordering::Relaxed);
+        let seeds: Vec<u64> = (0..self.threads).map(|_| self.rng.gen()).collect();
+        let searcher = &*self.searcher;
+        let board = self.board;
+        let depth = self.depth;
+        return thread::scope(|s| {
+            for (i, seed) in seeds.iter().cloned().enumerate().skip(1) {
+                // Odd helpers search one ply deeper so that the
+                // threads do not all finish the same iterations
+                // at the same time
+                s.spawn(move || searcher.iterative_deepening(&board, depth + i % 2,
+                                                             &mut StdRng::seed_from_u64(seed)));
+            }
+            let result = searcher.iterative_deepening(&board, depth,
+                                                      &mut StdRng::seed_from_u64(seeds[0]));
+            searcher.stop.store(true, Ordering::Relaxed);
+            result
+        });
+    }
+
+    // Start searching the position after the reply the table
+    // expects in a background thread, until stop_pondering
+    fn start_pondering(&mut self) {
+        let expected = match self.expected_reply() {
+            Some(m) => m,
+            None => return,
+        };
+        let mut board = self.board;
+        board.make_move(1 << expected);
+        if board.get_winner() != 0 {
+            return;
         }
+        let searcher = Arc::clone(&self.searcher);
+        let seed = self.rng.gen();
+        searcher.stop.store(false, Ordering::Relaxed);
+        let handle = thread::spawn(move || {
+            searcher.iterative_deepening(&board, board.empty_spaces() as usize,
+                                         &mut StdRng::seed_from_u64(seed));
+        });
+        self.pondering = Some((expected, handle));
+    }
+
+    // The reply the table expects in the current position
+    fn expected_reply(&self) -> Option<i64> {
+        return match self.searcher.table.probe(self.board.hash()) {
+            Some(entry) if entry.best_move >= 0
+                && self.board.get_moves() & (1 << entry.best_move) != 0 => Some(entry.best_move),
+            _ => None,
+        };
+    }
+
+    // Stops the ponder thread if there is one, returning the
+    // reply it expected
+    fn stop_pondering(&mut self) -> Option<i64> {
+        let (expected, handle) = self.pondering.take()?;
+        self.searcher.stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+        return Some(expected);
     }
 
-    pub fn search(&self, board: &mut BitBoard, depth: usize, 
-                  _alpha: i32, beta: i32) -> (i64, i32) {
+    // Negamax alpha-beta search returning the best move and its
+    // score from the perspective of the player to move
+    pub fn search(&self, board: &mut BitBoard, depth: usize,
+                  alpha: i32, beta: i32, rng: &mut dyn RngCore) -> (i64, i32) {
+        return self.searcher.search(board, depth, alpha, beta, rng);
+    }
+}
+
+impl Searcher {
+    fn stopped(&self) -> bool {
+        return self.stop.load(Ordering::Relaxed)
+               || self.interrupt.as_ref().is_some_and(|i| i.load(Ordering::Relaxed));
+    }
+
+    // Search board to depths 1 through depth, returning the
+    // result of the last completed search
+    fn iterative_deepening(&self, board: &BitBoard, depth: usize,
+                           rng: &mut dyn RngCore) -> (i64, i32) {
+        let alpha = -100000000;
+        let beta = 100000000;
+        let mut result = (-1, 0);
+        for d in 1..=depth {
+            let r = self.search(&mut board.clone(), d, alpha, beta, rng);
+            if self.stopped() {
+                break;
+            }
+            result = r;
+        }
+        return result;
+    }
+
+    fn search(&self, board: &mut BitBoard, depth: usize,
+              _alpha: i32, beta: i32, rng: &mut dyn RngCore) -> (i64, i32) {
         let mut alpha = _alpha;
+        if self.stopped() {
+            return (-1, 0);
+        }
         if depth == 0 {
-            return (-1, (self.eval)(board, self.me));
+            return (-1, self.eval.evaluate(board, board.to_move, rng));
         }
         let moves = board.get_moves();
         if moves == 0 {
-            if depth % 2 == 0 {
-                return (-1, (self.eval)(board, self.me));
-            } else {
-                return (-1, -(self.eval)(board, self.me));
+            return (-1, self.eval.evaluate(board, board.to_move, rng));
+        }
+        let hash = board.hash();
+        let mut table_move = -1;
+        if let Some(entry) = self.table.probe(hash) {
+            if entry.best_move >= 0 && moves & (1 << entry.best_move) != 0 {
+                table_move = entry.best_move;
+                if entry.depth >= depth {
+                    let cutoff = match entry.bound {
+                        Bound::Exact => true,
+                        Bound::Lower => entry.score >= beta,
+                        Bound::Upper => entry.score <= alpha,
+                    };
+                    if cutoff {
+                        return (entry.best_move, entry.score);
+                    }
+                }
             }
         }
         let mut result_move = -1;
-        BitBoard::iterate_moves(moves, &mut |next_move: u128, next_move_sf: i64| {
-           let mut next_b = board.clone();
+        let mut result_score = -100000000;
+        let mut try_move = |next_move: u128, next_move_sf: i64| -> bool {
+           let mut next_b = *board;
            next_b.make_move(next_move);
-           let (_, mut score) = self.search(&mut next_b, depth - 1, -beta, -alpha);
+           // No need to search a position that can only be drawn
+           let (_, mut score) = if next_b.get_winner() == 0 && next_b.draw_forced() {
+               (-1, 0)
+           } else {
+               self.search(&mut next_b, depth - 1, -beta, -alpha, rng)
+           };
            score = -score;
+           if score > result_score {
+               result_score = score;
+               result_move = next_move_sf;
+           }
            if score > alpha {
                alpha = score;
-               result_move = next_move_sf;
            }
 
            if alpha >= beta {
                return false;
            }
            return true;
+        };
+        // Try the best move from an earlier search first
+        if table_move == -1 || try_move(1 << table_move, table_move) {
+            let rest = if table_move == -1 { moves } else { moves & !(1 << table_move) };
+            BitBoard::iterate_moves(rest, &mut try_move);
+        }
+        if self.stopped() {
+            return (-1, 0);
+        }
+        let bound = if result_score <= _alpha {
+            Bound::Upper
+        } else if result_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(hash, TableEntry {
+            score: result_score,
+            best_move: result_move,
+            depth,
+            bound,
         });
-        return (result_move, alpha);
+        return (result_move, result_score);
     }
 
-    fn branching_mc(board: &mut BitBoard, branching: u8, me: i8) -> i32 {
+}
+
+impl SimpleSearchCenterAI {
+    #[allow(dead_code)]
+    fn branching_mc(board: &mut BitBoard, branching: u8, me: i8,
+                    rng: &mut dyn RngCore) -> i32 {
         if board.get_winner() == me {
             return 1;
         } else if board.get_winner() == -me {
@@ -83,18 +395,18 @@ impl SimpleSearchCenterAI {
         }
         let mut result = 0;
         BitBoard::iterate_moves(board.get_moves(), &mut |m: u128, _sf: i64| {
-            if rand::random::<u8>() < branching {
-                let mut n_b = board.clone();
+            if rng.gen::<u8>() < branching {
+                let mut n_b = *board;
                 n_b.make_move(m);
-                result += SimpleSearchCenterAI::branching_mc(&mut n_b, branching, me);
+                result += SimpleSearchCenterAI::branching_mc(&mut n_b, branching, me, rng);
             }
             return true;
         });
         return result;
     }
 
-    pub fn ab_then_mc(games: u64) -> Box<dyn Fn(&mut BitBoard, i8) -> i32> {
-        Box::new(move |_board: &mut BitBoard, me: i8| -> i32 {
+    pub fn ab_then_mc(games: u64) -> Box<dyn Evaluator> {
+        Box::new(move |_board: &mut BitBoard, me: i8, rng: &mut dyn RngCore| -> i32 {
               if _board.get_winner() == me {
                  return 50000;
               } else if _board.get_winner() == -me {
@@ -104,9 +416,9 @@ impl SimpleSearchCenterAI {
               }
               let mut result = 0;
               for _i in 0..games {
-                  let mut new_board = _board.clone();
+                  let mut new_board = *_board;
                   while new_board.get_winner() == 0 {
-                      new_board.make_move(BitBoard::random_move(new_board.get_moves()));
+                      new_board.make_move(BitBoard::random_move(new_board.get_moves(), rng));
                   } 
                   if new_board.get_winner() == me {
                       result += 1;
@@ -118,315 +430,23 @@ impl SimpleSearchCenterAI {
         })
     }
 
-    pub fn abriand_eval_1() -> Box<dyn Fn(&mut BitBoard, i8) -> i32> {
-        Box::new(move |board: &mut BitBoard, me: i8| -> i32 {
-              if board.get_winner() == me {
-                 return 50000;
-              } else if board.get_winner() == -me {
-                 return -50000;
-              }
-              let mut result : i32 = 0;
-              for i in 0..9 {
-                  if board.x_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result += (me as i32) * 1000;
-                  } else if board.o_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result -= (me as i32) * 1000;
-                  }
-              }
-              if board.x_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result += (me as i32) * 1000;
-              } else if board.o_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result -= (me as i32) * 1000;
-              }
-              
-              for i in [4, 13, 22, 31, 40, 49, 58, 67, 76].iter() {
-                  if board.x_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result += (me as i32) * 100;
-                  } else if board.o_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result -= (me as i32) * 100;
-                  }
-              }
-              return result;
-        })
+    pub fn abriand_eval_1() -> Box<dyn Evaluator> {
+        Box::new(LinearEvaluator::new(Weights::abriand_eval_1()))
     }
 
-    fn num_occupied_x(board: &mut BitBoard, cells: Vec<i32>) -> u32 {
-        let mut r = 0;
-        for i in cells.iter() {
-            if board.x_occupancy & ((1 as u128) << i) != 0 {
-                r += 1;
-            }
-        }
-        return r;
+    pub fn diagonal() -> Box<dyn Evaluator> {
+        Box::new(LinearEvaluator::new(Weights::diagonal()))
     }
 
-    fn num_occupied_o(board: &mut BitBoard, cells: Vec<i32>) -> u32 {
-        let mut r = 0;
-        for i in cells.iter() {
-            if board.o_occupancy & ((1 as u128) << i) != 0 {
-                r += 1;
-            }
-        }
-        return r;
+    pub fn diagonal2() -> Box<dyn Evaluator> {
+        Box::new(LinearEvaluator::new(Weights::diagonal2()))
     }
 
-    pub fn diagonal() -> Box<dyn Fn(&mut BitBoard, i8) -> i32> {
-        Box::new(move |board: &mut BitBoard, me: i8| -> i32 {
-              if board.get_winner() == me {
-                 return 50000;
-              } else if board.get_winner() == -me {
-                 return -50000;
-              }
-              let mut partial_credit;
-              let mut result : i32 = 0;
-              for i in 0..9 {
-                  if i == 4 {
-                      partial_credit = 800;
-                  } else {
-                      partial_credit = 400;
-                  }
-                  if board.x_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result += (me as i32) * 1000;
-                  } else if board.o_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result -= (me as i32) * 1000;
-                  } else {
-                      for j in 0..3 {
-                        if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 0 {
-                          result += (me as i32) * partial_credit;
-                        } 
-                        if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 0 {
-                          result -= (me as i32) * partial_credit;
-                        }
-                      }
-                      for j in [0, 3, 6].iter() {
-                        if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 0 {
-                          result += (me as i32) * partial_credit;
-                        }
-                        if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 0 {
-                          result -= (me as i32) * partial_credit;
-                        }
-                      }
-                      if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i, 9*i + 4, 9*i + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i, 9*i + 4, 9*i + 8]) == 0 {
-                        result += (me as i32) * partial_credit;
-                      } 
-                      if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i, 9*i + 4, 9*i + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i, 9*i + 4, 9*i + 8]) == 0 {
-                        result -= (me as i32) * partial_credit;
-                      }
-                      if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 0 {
-                        result += (me as i32) * partial_credit;
-                      }
-                      if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 0 {
-                        result -= (me as i32) * partial_credit;
-                      }
-                  }
-              }
-              let partial_credit_l2 = 6000;
-              for j in 0..3 {
-                if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 0 {
-                  result += (me as i32) * partial_credit_l2;
-                } 
-                if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 0 {
-                  result -= (me as i32) * partial_credit_l2;
-                }
-              }
-              for j in [0, 3, 6].iter() {
-                if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 0 {
-                  result += (me as i32) * partial_credit_l2;
-                }
-                if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 0 {
-                  result -= (me as i32) * partial_credit_l2;
-                }
-              }
-              if SimpleSearchCenterAI::num_occupied_x(board, vec![81, 81 + 4, 81 + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81, 81 + 4, 81 + 8]) == 0 {
-                result += (me as i32) * partial_credit_l2;
-              } 
-              if SimpleSearchCenterAI::num_occupied_o(board, vec![81, 81 + 4, 81 + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81, 81 + 4, 81 + 8]) == 0 {
-                result -= (me as i32) * partial_credit_l2;
-              }
-              if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + 2, 81 + 4, 81 + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + 2, 81 + 4, 81 + 6]) == 0 {
-                result += (me as i32) * partial_credit_l2;
-              }
-              if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + 2, 81 + 4, 81 + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + 2, 81 + 4, 81 + 6]) == 0 {
-                result -= (me as i32) * partial_credit_l2;
-              }
-              if board.x_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result += (me as i32) * 1000;
-              } else if board.o_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result -= (me as i32) * 1000;
-              }
-              for i in [4, 13, 22, 31, 40, 49, 58, 67, 76].iter() {
-                  if board.x_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result += (me as i32) * 100;
-                  } else if board.o_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result -= (me as i32) * 100;
-                  }
-              }
-              
-              
-              for i in 0..9 {
-                  for j in [0, 2, 4, 6, 8].iter() {
-                    if board.x_occupancy & ((1 as u128) << (9 * i + j)) != 0 as u128 {
-                        result += (me as i32) * 100;
-                    } else if board.o_occupancy & ((1 as u128) << (9 * i + j)) != 0 as u128 {
-                        result -= (me as i32) * 100;
-                    }
-                  }
-              }
-                  /*for j in [0, 2, 4, 6, 8].iter() {
-                    if board.x_occupancy & ((1 as u128) << (81 + j)) != 0 as u128 {
-                        result += (me as i32) * 500;
-                    } else if board.o_occupancy & ((1 as u128) << (81 + j)) != 0 as u128 {
-                        result -= (me as i32) * 500;
-                    }
-                  }*/
-              return result + ((rand::random::<i32>() % 200) - 100);
-        })
+    // Evaluator using weights read from path
+    pub fn weights_file(path: &str) -> Result<Box<dyn Evaluator>, Error> {
+        return Ok(Box::new(LinearEvaluator::new(Weights::load(path)?)));
     }
 
-    pub fn diagonal2() -> Box<dyn Fn(&mut BitBoard, i8) -> i32> {
-        Box::new(move |board: &mut BitBoard, me: i8| -> i32 {
-              if board.get_winner() == me {
-                 return 50000;
-              } else if board.get_winner() == -me {
-                 return -50000;
-              }
-              let mut partial_credit;
-              let mut result : i32 = 0;
-              for i in 0..9 {
-                  if i == 4 {
-                      partial_credit = 600;
-                  } else {
-                      partial_credit = 400;
-                  }
-                  if board.x_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result += (me as i32) * 1000;
-                  } else if board.o_occupancy & ((1 as u128) << (81 + i)) != 0 {
-                      result -= (me as i32) * 1000;
-                  } else {
-                      for j in 0..3 {
-                        if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 0 {
-                          result += (me as i32) * partial_credit;
-                        } 
-                        if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 3, 9*i + j + 6]) == 0 {
-                          result -= (me as i32) * partial_credit;
-                        }
-                      }
-                      for j in [0, 3, 6].iter() {
-                        if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 0 {
-                          result += (me as i32) * partial_credit;
-                        }
-                        if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + j, 9*i + j + 1, 9*i + j + 2]) == 0 {
-                          result -= (me as i32) * partial_credit;
-                        }
-                      }
-                      if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i, 9*i + 4, 9*i + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i, 9*i + 4, 9*i + 8]) == 0 {
-                        result += (me as i32) * partial_credit;
-                      } 
-                      if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i, 9*i + 4, 9*i + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i, 9*i + 4, 9*i + 8]) == 0 {
-                        result -= (me as i32) * partial_credit;
-                      }
-                      if SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 0 {
-                        result += (me as i32) * partial_credit;
-                      }
-                      if SimpleSearchCenterAI::num_occupied_o(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![9*i + 2, 9*i + 4, 9*i + 6]) == 0 {
-                        result -= (me as i32) * partial_credit;
-                      }
-                  }
-              }
-              let partial_credit_l2 = 6000;
-              for j in 0..3 {
-                if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 0 {
-                  result += (me as i32) * partial_credit_l2;
-                } 
-                if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 3, 81 + j + 6]) == 0 {
-                  result -= (me as i32) * partial_credit_l2;
-                }
-              }
-              for j in [0, 3, 6].iter() {
-                if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 0 {
-                  result += (me as i32) * partial_credit_l2;
-                }
-                if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + j, 81 + j + 1, 81 + j + 2]) == 0 {
-                  result -= (me as i32) * partial_credit_l2;
-                }
-              }
-              if SimpleSearchCenterAI::num_occupied_x(board, vec![81, 81 + 4, 81 + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81, 81 + 4, 81 + 8]) == 0 {
-                result += (me as i32) * partial_credit_l2;
-              } 
-              if SimpleSearchCenterAI::num_occupied_o(board, vec![81, 81 + 4, 81 + 8]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81, 81 + 4, 81 + 8]) == 0 {
-                result -= (me as i32) * partial_credit_l2;
-              }
-              if SimpleSearchCenterAI::num_occupied_x(board, vec![81 + 2, 81 + 4, 81 + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_o(board, vec![81 + 2, 81 + 4, 81 + 6]) == 0 {
-                result += (me as i32) * partial_credit_l2;
-              }
-              if SimpleSearchCenterAI::num_occupied_o(board, vec![81 + 2, 81 + 4, 81 + 6]) == 2 
-                           && SimpleSearchCenterAI::num_occupied_x(board, vec![81 + 2, 81 + 4, 81 + 6]) == 0 {
-                result -= (me as i32) * partial_credit_l2;
-              }
-              if board.x_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result += (me as i32) * 1000;
-              } else if board.o_occupancy & ((1 as u128) << (81 + 4)) != 0 {
-                  result -= (me as i32) * 1000;
-              }
-              for i in [4, 13, 22, 31, 40, 49, 58, 67, 76].iter() {
-                  if board.x_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result += (me as i32) * 100;
-                  } else if board.o_occupancy & ((1 as u128) << i) != 0 as u128 {
-                      result -= (me as i32) * 100;
-                  }
-              }
-              
-              
-              for i in 0..9 {
-                  for j in [0, 2, 4, 6, 8].iter() {
-                    if board.x_occupancy & ((1 as u128) << (9 * i + j)) != 0 as u128 {
-                        result += (me as i32) * 100;
-                    } else if board.o_occupancy & ((1 as u128) << (9 * i + j)) != 0 as u128 {
-                        result -= (me as i32) * 100;
-                    }
-                  }
-              }
-                  /*for j in [0, 2, 4, 6, 8].iter() {
-                    if board.x_occupancy & ((1 as u128) << (81 + j)) != 0 as u128 {
-                        result += (me as i32) * 500;
-                    } else if board.o_occupancy & ((1 as u128) << (81 + j)) != 0 as u128 {
-                        result -= (me as i32) * 500;
-                    }
-                  }*/
-              return result + ((rand::random::<i32>() % 200) - 100);
-        })
-    }
     /*pub fn diagonal() -> Box<dyn Fn(&mut Board, Player) -> i32> {
         Box::new(move |board: &mut Board, me: Player| -> i32 {
               let opponent = match me {
@@ -457,4 +477,124 @@ impl SimpleSearchCenterAI {
               return result;
         })
     }*/
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+     #[test]
+     fn test_parallel_search_finds_win() {
+         // O can win the game by playing 23
+         let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29];
+         for threads in [1, 4].iter() {
+             let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                    4, *threads, 0);
+             for m in &moves {
+                 ai.board.make_move(1 << m);
+             }
+             let (m, score) = ai.parallel_search();
+             assert_eq!(score, 50000);
+             let mut board = ai.board;
+             board.make_move(1 << m);
+             assert_eq!(board.get_winner(), -1);
+         }
+     }
+
+     #[test]
+     #[ignore]
+     fn test_search_scaling() {
+         let moves = vec![40, 36, 4, 37, 13];
+         println!("Threads\tSeconds");
+         for threads in [1, 2, 4, 8].iter() {
+             let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(),
+                                                    10, *threads, 0);
+             for m in &moves {
+                 ai.board.make_move(1 << m);
+             }
+             let now = Instant::now();
+             ai.parallel_search();
+             println!("{}\t{}", threads, now.elapsed().as_secs_f64());
+         }
+     }
+
+     #[test]
+     fn test_switches_to_solver() {
+         // O to move and can win by playing 23. The solver finds
+         // this quickly even with most of the board empty
+         let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29];
+         let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                2, 1, 81);
+         assert!(ai.set_opening(&moves[..moves.len() - 1]));
+         let m = ai.get_move(moves[moves.len() - 1] as i64).unwrap();
+         let mut board = BitBoard::new();
+         for i in &moves {
+             board.make_move(1 << i);
+         }
+         board.make_move(1 << m);
+         assert_eq!(board.get_winner(), -1);
+         assert_eq!(ai.last_score(), Some(50000));
+     }
+
+     #[test]
+     fn test_game_over() {
+         // O won with 23, so neither the search nor the solver
+         // has a move to make
+         let moves = vec![0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23];
+         for solve_below in [0, 81].iter() {
+             let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                    2, 1, *solve_below);
+             assert!(ai.set_opening(&moves));
+             assert_eq!(ai.get_move(-1), Err(Error::GameOver));
+             assert_eq!(ai.history.len(), moves.len());
+         }
+     }
+
+     #[test]
+     fn test_book_moves() {
+         let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                1, 1, 0);
+         assert_eq!(ai.get_move(-1), Ok(40));
+         let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                1, 1, 0);
+         ai.set_book("1 40 36 0\n".parse().unwrap());
+         assert_eq!(ai.get_move(40), Ok(36));
+         assert_eq!(ai.last_score(), None);
+         // Out of book, so the move is searched
+         assert!(ai.get_move(0).unwrap() != -1);
+         assert!(ai.last_score().is_some());
+         let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                1, 1, 0);
+         ai.set_book("1 40 36 0\n".parse().unwrap());
+         assert!(ai.set_opening(&[40, 36]));
+         assert_eq!(ai.get_move(-1), Ok(0));
+         assert!(!ai.set_opening(&[80]));
+         assert!(!ai.set_opening(&[200]));
+     }
+
+     #[test]
+     fn test_ponder() {
+         let mut ai = SimpleSearchCenterAI::new(SimpleSearchCenterAI::abriand_eval_1(),
+                                                3, 1, 0);
+         ai.set_book(Book::new());
+         assert!(ai.set_ponder(true));
+         let mut board = BitBoard::new();
+         let m = ai.get_move(-1).unwrap();
+         board.make_move(1 << m);
+         let expected = ai.pondering.as_ref().unwrap().0;
+         assert!(board.get_moves() & (1 << expected) != 0);
+         board.make_move(1 << expected);
+         // A ponder hit
+         let m = ai.get_move(expected).unwrap();
+         assert!(board.get_moves() & (1 << m) != 0);
+         board.make_move(1 << m);
+         assert!(ai.pondering.is_some());
+         assert!(ai.set_ponder(false));
+         assert!(ai.pondering.is_none());
+         let reply = BitBoard::random_move(board.get_moves(), &mut StdRng::seed_from_u64(0));
+         board.make_move(reply);
+         let m = ai.get_move(reply.trailing_zeros() as i64).unwrap();
+         assert!(board.get_moves() & (1 << m) != 0);
+         assert!(ai.pondering.is_none());
+     }
+}
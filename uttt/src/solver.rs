@@ -0,0 +1,259 @@
+use crate::bitboard::BitBoard;
+use crate::transposition::{TranspositionTable, TableEntry, Bound};
+
+// The score of winning immediately. Winning after p plies scores
+// WIN - p, losing after p plies scores -(WIN - p) and a draw
+// scores 0, so the fastest win and the slowest loss score highest
+pub const WIN: i32 = 1000;
+
+// The proven result of a position for the player to move
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Outcome {
+    // The player to move wins after this many plies
+    Win(u32),
+    // The player to move loses after this many plies
+    Loss(u32),
+    Draw,
+}
+
+impl Outcome {
+    pub fn from_score(score: i32) -> Outcome {
+        if score > 0 {
+            return Outcome::Win((WIN - score) as u32);
+        } else if score < 0 {
+            return Outcome::Loss((WIN + score) as u32);
+        }
+        return Outcome::Draw;
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Win(n) => write!(f, "win in {}", n),
+            Outcome::Loss(n) => write!(f, "loss in {}", n),
+            Outcome::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+// Searches positions to the end of the game to find their exact
+// result. Only practical once few empty spaces remain
+pub struct Solver {
+    table: TranspositionTable,
+    // Positions searched by the last call to solve
+    pub nodes: u64,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            table: TranspositionTable::new(20),
+            nodes: 0,
+        }
+    }
+
+    // Returns the best move in board and its proven outcome
+    // for the player to move. Returns -1 as the move if the
+    // game is already over
+    pub fn solve(&mut self, board: &BitBoard) -> (i64, Outcome) {
+        self.nodes = 0;
+        let (m, score) = self.search(board, 0, -WIN, WIN);
+        return (m, Outcome::from_score(score));
+    }
+
+    // Negamax alpha-beta search with scores from the perspective
+    // of the player to move and distances counted from the root
+    fn search(&mut self, board: &BitBoard, ply: i32,
+              _alpha: i32, _beta: i32) -> (i64, i32) {
+        self.nodes += 1;
+        match board.get_winner() {
+            0 => (),
+            -2 => return (-1, 0),
+            // The player who just moved won
+            _ => return (-1, -(WIN - ply)),
+        }
+        if ply > 0 && board.draw_forced() {
+            return (-1, 0);
+        }
+        // Neither player can do better than winning next move
+        // or worse than losing now
+        let mut alpha = _alpha.max(-(WIN - ply));
+        let beta = _beta.min(WIN - ply - 1);
+        if alpha >= beta {
+            return (-1, alpha);
+        }
+        let moves = board.get_moves();
+        let hash = board.hash();
+        let mut table_move = -1;
+        if let Some(entry) = self.table.probe(hash) {
+            if entry.best_move >= 0 && moves & (1 << entry.best_move) != 0 {
+                table_move = entry.best_move;
+                let score = Solver::score_from_table(entry.score, ply);
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    return (entry.best_move, score);
+                }
+            }
+        }
+        // Try the best move from an earlier search, then moves that
+        // capture a small board, then the rest
+        let mine = if board.to_move == 1 {
+            board.x_occupancy
+        } else {
+            board.o_occupancy
+        };
+        let mut captures = 0;
+        BitBoard::iterate_moves(moves, &mut |m: u128, sf: i64| {
+            let square = sf as usize / 9;
+            if BitBoard::has_line(((mine | m) >> (9 * square)) & 0x1ff) {
+                captures |= m;
+            }
+            return true;
+        });
+        let first = if table_move == -1 { 0 } else { 1 << table_move };
+        let ordered = [first, captures & !first, moves & !captures & !first];
+        let mut result_move = -1;
+        let mut result_score = -WIN;
+        'outer: for group in ordered.iter() {
+            let mut remaining = *group;
+            while remaining != 0 {
+                let sf = remaining.trailing_zeros() as i64;
+                remaining &= remaining - 1;
+                let mut next_b = *board;
+                next_b.make_move(1 << sf);
+                let (_, mut score) = self.search(&next_b, ply + 1, -beta, -alpha);
+                score = -score;
+                if score > result_score || result_move == -1 {
+                    result_score = score;
+                    result_move = sf;
+                }
+                if score > alpha {
+                    alpha = score;
+                }
+                if alpha >= beta {
+                    break 'outer;
+                }
+            }
+        }
+        let bound = if result_score <= _alpha {
+            Bound::Upper
+        } else if result_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(hash, TableEntry {
+            score: Solver::score_to_table(result_score, ply),
+            best_move: result_move,
+            depth: 0,
+            bound,
+        });
+        return (result_move, result_score);
+    }
+
+    // Scores are stored in the table relative to the position
+    // they belong to rather than to the root
+    fn score_to_table(score: i32, ply: i32) -> i32 {
+        if score > 0 {
+            return score + ply;
+        } else if score < 0 {
+            return score - ply;
+        }
+        return 0;
+    }
+
+    fn score_from_table(score: i32, ply: i32) -> i32 {
+        if score > 0 {
+            return score - ply;
+        } else if score < 0 {
+            return score + ply;
+        }
+        return 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+     fn play(moves: &[usize]) -> BitBoard {
+         let mut board = BitBoard::new();
+         for m in moves {
+             assert!(board.get_moves() & (1 << m) != 0);
+             board.make_move(1 << m);
+         }
+         return board;
+     }
+
+     #[test]
+     fn test_win_in_one() {
+         let board = play(&[0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29]);
+         let (m, outcome) = Solver::new().solve(&board);
+         assert_eq!(outcome, Outcome::Win(1));
+         let mut next = board;
+         next.make_move(1 << m);
+         assert_eq!(next.get_winner(), -1);
+     }
+
+     // Plain minimax without pruning or the table
+     fn minimax(board: &BitBoard, ply: i32) -> i32 {
+         match board.get_winner() {
+             0 => (),
+             -2 => return 0,
+             _ => return -(WIN - ply),
+         }
+         let mut best = -WIN;
+         BitBoard::iterate_moves(board.get_moves(), &mut |m: u128, _sf: i64| {
+             let mut next = *board;
+             next.make_move(m);
+             best = best.max(-minimax(&next, ply + 1));
+             return true;
+         });
+         return best;
+     }
+
+     #[test]
+     fn test_matches_minimax() {
+         let mut solver = Solver::new();
+         let mut rng = StdRng::seed_from_u64(0);
+         let mut checked = 0;
+         while checked < 20 {
+             let mut board = BitBoard::new();
+             while board.get_winner() == 0 && board.empty_spaces() > 9 {
+                 board.make_move(BitBoard::random_move(board.get_moves(), &mut rng));
+             }
+             if board.get_winner() != 0 {
+                 continue;
+             }
+             let expected = minimax(&board, 0);
+             let (m, outcome) = solver.solve(&board);
+             assert_eq!(outcome, Outcome::from_score(expected));
+             // The chosen move keeps the outcome
+             let mut next = board;
+             next.make_move(1 << m);
+             assert_eq!(-minimax(&next, 1), expected);
+             checked += 1;
+         }
+     }
+
+     #[test]
+     fn test_solved_game_over() {
+         let board = play(&[0, 3, 27, 4, 36, 5, 46, 13, 37, 12, 28, 14, 47, 22, 38, 21, 29, 23]);
+         assert_eq!(Solver::new().solve(&board), (-1, Outcome::Loss(0)));
+     }
+}
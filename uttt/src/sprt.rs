@@ -0,0 +1,182 @@
+// Sequential probability ratio test for deciding whether a
+// candidate AI is stronger than a baseline, from pairs of games
+// in which each plays the same opening once as X.
+//
+// H0 is that the candidate is elo0 stronger than the baseline
+// and H1 that it is elo1 stronger. After every pair the log
+// likelihood ratio of H1 to H0 is compared to bounds set by alpha,
+// the chance of accepting H1 when H0 is true, and beta, the chance
+// of accepting H0 when H1 is true. The ratio uses the normal
+// approximation of the candidate's score per pair, counting pairs
+// by the points the candidate won in them (0, 1/2, 1, 3/2 or 2),
+// so that the correlation between the games of a pair is kept
+use crate::error::Error;
+use std::fmt;
+
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+// What the test has decided so far
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Decision {
+    // Neither bound has been reached
+    Continue,
+    // H0: the candidate isn't elo1 stronger
+    AcceptH0,
+    // H1: the candidate isn't only elo0 stronger
+    AcceptH1,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Decision::Continue => "none",
+            Decision::AcceptH0 => "H0",
+            Decision::AcceptH1 => "H1",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+// The expected score per game of a player elo stronger than
+// its opponent
+pub fn elo_to_score(elo: f64) -> f64 {
+    return 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+}
+
+// The Elo difference giving an expected score per game of score
+pub fn score_to_elo(score: f64) -> f64 {
+    return -400.0 * (1.0 / score - 1.0).log10();
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        return Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+    }
+}
+
+impl Sprt {
+    // Fails unless elo0 < elo1 and alpha and beta are between 0
+    // and 1, without which the bounds and LLR mean nothing
+    pub fn new(_elo0: f64, _elo1: f64, _alpha: f64, _beta: f64) -> Result<Sprt, Error> {
+        if !(_elo0.is_finite() && _elo1.is_finite()) || _elo0 >= _elo1 {
+            return Err(Error::Invalid(format!("elo0 {} must be less than elo1 {}",
+                                              _elo0, _elo1)));
+        }
+        for (name, p) in [("alpha", _alpha), ("beta", _beta)] {
+            if !(p > 0.0 && p < 1.0) {
+                return Err(Error::Invalid(format!("{} {} must be between 0 and 1", name, p)));
+            }
+        }
+        return Ok(Sprt {
+            elo0: _elo0,
+            elo1: _elo1,
+            alpha: _alpha,
+            beta: _beta,
+        });
+    }
+
+    // The LLRs at or below which H0 and at or above which H1
+    // is accepted
+    pub fn bounds(&self) -> (f64, f64) {
+        return ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln());
+    }
+
+    // The candidate's mean score per game over pairs, where pairs[i]
+    // is the number of pairs in which it won i half points
+    pub fn score(pairs: &[u32; 5]) -> f64 {
+        let n: u32 = pairs.iter().sum();
+        if n == 0 {
+            return 0.5;
+        }
+        let points: f64 = pairs.iter().enumerate().map(|(i, c)| i as f64 / 4.0 * *c as f64).sum();
+        return points / n as f64;
+    }
+
+    // The log likelihood ratio of H1 to H0 after pairs, counted
+    // as for score
+    pub fn llr(&self, pairs: &[u32; 5]) -> f64 {
+        // One imaginary pair spread over the pairs with a winner, so
+        // that the variance of a run of identical results isn't 0
+        // and the first few pairs can't decide the test
+        const PRIOR: [f64; 5] = [0.25, 0.25, 0.0, 0.25, 0.25];
+        let counts: Vec<f64> = pairs.iter().zip(PRIOR.iter()).map(|(c, p)| *c as f64 + p)
+            .collect();
+        let n: f64 = counts.iter().sum();
+        let mean = counts.iter().enumerate().map(|(i, c)| i as f64 / 4.0 * c).sum::<f64>() / n;
+        let var = counts.iter().enumerate()
+            .map(|(i, c)| (i as f64 / 4.0 - mean).powi(2) * c).sum::<f64>() / n;
+        let (s0, s1) = (elo_to_score(self.elo0), elo_to_score(self.elo1));
+        return n * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * var);
+    }
+
+    // What to do after pairs, counted as for score
+    pub fn decide(&self, pairs: &[u32; 5]) -> Decision {
+        let llr = self.llr(pairs);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            return Decision::AcceptH1;
+        }
+        if llr <= lower {
+            return Decision::AcceptH0;
+        }
+        return Decision::Continue;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_elo() {
+         assert_eq!(elo_to_score(0.0), 0.5);
+         assert!((elo_to_score(400.0) - 10.0 / 11.0).abs() < 1e-9);
+         assert!((score_to_elo(elo_to_score(-35.0)) + 35.0).abs() < 1e-9);
+     }
+
+     #[test]
+     fn test_sprt() {
+         let sprt = Sprt::default();
+         let (lower, upper) = sprt.bounds();
+         assert!((lower + 2.944).abs() < 0.001);
+         assert!((upper - 2.944).abs() < 0.001);
+         assert_eq!(sprt.decide(&[0, 0, 0, 0, 0]), Decision::Continue);
+         // Even results favor neither hypothesis much
+         assert_eq!(sprt.decide(&[10, 20, 40, 20, 10]), Decision::Continue);
+         assert!(sprt.llr(&[10, 20, 40, 20, 10]) < 0.0);
+         // A candidate winning more pairs than it loses is
+         // accepted, and one losing more of them rejected
+         assert_eq!(sprt.decide(&[15, 30, 120, 90, 45]), Decision::AcceptH1);
+         assert_eq!(sprt.decide(&[45, 90, 120, 30, 15]), Decision::AcceptH0);
+         assert_eq!(sprt.decide(&[0, 0, 0, 0, 1]), Decision::Continue);
+         assert_eq!(Sprt::new(0.0, 50.0, 0.05, 0.05).unwrap().decide(&[0, 0, 0, 0, 10]),
+                    Decision::AcceptH1);
+         assert_eq!(Sprt::score(&[0, 0, 2, 0, 2]), 0.75);
+     }
+
+     #[test]
+     fn test_new() {
+         assert_eq!(Sprt::new(0.0, 5.0, 0.05, 0.05), Ok(Sprt::default()));
+         assert!(Sprt::new(5.0, 0.0, 0.05, 0.05).is_err());
+         assert!(Sprt::new(5.0, 5.0, 0.05, 0.05).is_err());
+         assert!(Sprt::new(0.0, f64::NAN, 0.05, 0.05).is_err());
+         assert!(Sprt::new(0.0, 5.0, 0.0, 0.05).is_err());
+         assert!(Sprt::new(0.0, 5.0, 0.05, 1.0).is_err());
+         assert!(Sprt::new(0.0, 5.0, f64::NAN, 0.05).is_err());
+     }
+}
@@ -0,0 +1,664 @@
+use crate::adjudication::{Adjudication, Adjudicator};
+use crate::ai::AI;
+use crate::board::{Board, Player};
+use crate::engine;
+use crate::error::Error;
+use crate::eval::Weights;
+use crate::jsonl;
+use crate::pipeai::PipeAI;
+use crate::record::{GameRecord, Termination};
+use crate::rules::Rules;
+use crate::sprt::{self, Decision, Sprt};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+// An AI taking part in a tournament
+pub struct Entrant {
+    pub name: String,
+    // Creates the AI afresh for every game. If it fails,
+    // the game is lost without being played
+    pub make_ai: Box<dyn Fn() -> Result<Box<dyn AI>, Error>>,
+    // Whether the AI thinks on its opponent's time
+    pub ponder: bool,
+}
+
+impl Entrant {
+    pub fn new(_name: &str, _make_ai: Box<dyn Fn() -> Result<Box<dyn AI>, Error>>) -> Entrant {
+        Entrant {
+            name: _name.to_string(),
+            make_ai: _make_ai,
+            ponder: false,
+        }
+    }
+
+    // The entrant a spec describes, named by the spec. A spec is
+    //
+    //   search[:<weights file>][:<option>=<value>,...]
+    //   mcts[:<option>=<value>,...]
+    //   pipe:<command>[ <argument>...]
+    //
+    // where the options are those uttt-engine declares for the
+    // AI, like search:tuned.txt:depth=8 or mcts:playouts=50000.
+    // The weights are read and the options checked here, the
+    // engine of a pipe is only started for each game
+    pub fn from_spec(spec: &str) -> Result<Entrant, Error> {
+        let bad = |why: &str| Error::Parse(format!("bad entrant {}: {}", spec, why));
+        if let Some(command) = spec.strip_prefix("pipe:") {
+            let mut words = command.split_whitespace().map(|w| w.to_string());
+            let cmd = words.next().ok_or_else(|| bad("no command"))?;
+            let args: Vec<String> = words.collect();
+            return Ok(Entrant::new(spec, Box::new(move || {
+                let ai = PipeAI::new(cmd.clone(), args.clone())?;
+                return Ok(Box::new(ai) as Box<dyn AI>);
+            })));
+        }
+        let (kind, rest) = spec.split_once(':').unwrap_or((spec, ""));
+        // The options are the last part, if it has an =
+        let (file, settings) = match rest.rsplit_once(':') {
+            Some((file, settings)) if settings.contains('=') => (file, settings),
+            _ if rest.contains('=') => ("", rest),
+            _ => (rest, ""),
+        };
+        let mut server = match (kind, file) {
+            ("search", "") => engine::search_server(Weights::diagonal2(), None),
+            ("search", path) => engine::search_server(Weights::load(path)?, None),
+            ("mcts", "") => engine::mcts_server(),
+            ("mcts", _) => return Err(bad("mcts has no weights")),
+            _ => return Err(bad("expected search, mcts or pipe")),
+        };
+        for setting in settings.split(',').filter(|s| !s.is_empty()) {
+            let (name, value) = setting.split_once('=')
+                .ok_or_else(|| bad(&format!("{} isn't <option>=<value>", setting)))?;
+            // Games are played under the tournament's rules
+            if name.eq_ignore_ascii_case("Rules") {
+                return Err(bad("the rules are those of the tournament"));
+            }
+            server.set_option(name, value).map_err(|why| bad(&why.to_string()))?;
+        }
+        return Ok(Entrant::new(spec, Box::new(move || Ok(server.new_ai()))));
+    }
+}
+
+// One game of a tournament: the indices of the entrants playing
+// X and O and of the opening they start from
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct Pairing {
+    pub x: usize,
+    pub o: usize,
+    pub opening: usize,
+}
+
+// Who plays whom. Whenever two entrants meet they play each
+// opening once with each of them as X
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Format {
+    // Every entrant plays every other
+    RoundRobin,
+    // The first entrant, the candidate, plays every other, and
+    // the others don't play each other
+    Gauntlet,
+    // Every round pairs entrants with similar scores that haven't
+    // met yet. With an odd number of entrants, the lowest ranked
+    // one that hasn't sat out a round yet sits it out, scoring
+    // nothing
+    Swiss,
+    // Entrants meet in matches, the loser of each is out. The
+    // first entrants listed are seeded highest: they meet the
+    // lowest seeds and sit out the round when there is an odd
+    // number left
+    Knockout,
+    // The first entrant, the candidate, plays pairs of games with
+    // the second, the baseline, until the SPRT of sprt.rs decides
+    // whether it is stronger. Other entrants don't play
+    Sprt,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        return Format::RoundRobin;
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::RoundRobin => "round-robin",
+            Format::Gauntlet => "gauntlet",
+            Format::Swiss => "swiss",
+            Format::Knockout => "knockout",
+            Format::Sprt => "sprt",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        return match s {
+            "round-robin" => Ok(Format::RoundRobin),
+            "gauntlet" => Ok(Format::Gauntlet),
+            "swiss" => Ok(Format::Swiss),
+            "knockout" => Ok(Format::Knockout),
+            "sprt" => Ok(Format::Sprt),
+            _ => Err(Error::Parse(format!("bad tournament format {}", s))),
+        };
+    }
+}
+
+// The results of one entrant
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Standing {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    // One point for a win and half for a draw
+    pub score: f32,
+}
+
+// Extra pairs of games a tied knockout match is extended by
+// before the higher seed goes through
+const KNOCKOUT_TIEBREAKS: usize = 3;
+
+pub struct Tournament {
+    pub entrants: Vec<Entrant>,
+    pub format: Format,
+    // Every pairing of entrants plays each opening once with
+    // each entrant as X
+    pub openings: Vec<Vec<usize>>,
+    // Number of times the whole schedule is played. For a Swiss
+    // tournament the number of rounds, and for a knockout the
+    // number of times the games of every match are played. For an
+    // SPRT the most pairs of games played before giving up
+    pub rounds: usize,
+    pub sprt: Sprt,
+    // Seed of the first game, later games use the following seeds
+    pub first_seed: u64,
+    pub adjudication: Adjudication,
+    // The rules every game is played under
+    pub rules: Rules,
+    // File the record of every game is appended to
+    pub records: Option<String>,
+    // File a JSON line for every game, and one summing up the
+    // tournament at its end, is appended to (see JSON.md)
+    pub json: Option<String>,
+    // Games already played, for example by an earlier run of the
+    // tournament that was stopped, as read back from its records.
+    // A game of the schedule with the same players and seed as one
+    // of these isn't played again
+    pub completed: Vec<GameRecord>,
+}
+
+impl Tournament {
+    // A single round robin from the empty board without
+    // adjudication or records
+    pub fn new(_entrants: Vec<Entrant>, _first_seed: u64) -> Tournament {
+        Tournament {
+            entrants: _entrants,
+            format: Format::RoundRobin,
+            openings: vec![Vec::new()],
+            rounds: 1,
+            sprt: Sprt::default(),
+            first_seed: _first_seed,
+            adjudication: Adjudication::default(),
+            rules: Rules::default(),
+            records: None,
+            json: None,
+            completed: Vec::new(),
+        }
+    }
+
+    // The games of one round of the round robin
+    pub fn round_robin_pairings(&self) -> Vec<Pairing> {
+        let mut pairings = Vec::new();
+        for opening in 0..self.openings.len() {
+            for x in 0..self.entrants.len() {
+                for o in 0..self.entrants.len() {
+                    if x != o {
+                        pairings.push(Pairing { x, o, opening });
+                    }
+                }
+            }
+        }
+        return pairings;
+    }
+
+    // The games of one round of the gauntlet
+    pub fn gauntlet_pairings(&self) -> Vec<Pairing> {
+        let mut pairings = Vec::new();
+        for o in 1..self.entrants.len() {
+            pairings.extend(self.match_pairings(0, o));
+        }
+        return pairings;
+    }
+
+    // The games of a match between entrants a and b
+    pub fn match_pairings(&self, a: usize, b: usize) -> Vec<Pairing> {
+        let mut pairings = Vec::new();
+        for opening in 0..self.openings.len() {
+            pairings.push(Pairing { x: a, o: b, opening });
+            pairings.push(Pairing { x: b, o: a, opening });
+        }
+        return pairings;
+    }
+
+    // The games of the next round of a Swiss tournament that has
+    // played the games in records
+    pub fn swiss_pairings(&self, records: &[GameRecord]) -> Vec<Pairing> {
+        let scores = self.scores(records);
+        let name = |i: usize| self.entrants[i].name.as_str();
+        let played = |i: usize| records.iter().filter(|r| r.x == name(i) || r.o == name(i)).count();
+        let met = |a: usize, b: usize| records.iter().any(|r| (r.x == name(a) && r.o == name(b))
+                                                             || (r.x == name(b) && r.o == name(a)));
+        // Best first, ties in the order the entrants are listed
+        let mut ranked: Vec<usize> = (0..self.entrants.len()).collect();
+        ranked.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap());
+        if ranked.len() % 2 == 1 {
+            // Entrants that sat out have played fewer games
+            let most = ranked.iter().map(|i| played(*i)).max().unwrap_or(0);
+            let bye = ranked.iter().rposition(|i| played(*i) == most).unwrap();
+            println!("{} sits out the round", name(ranked[bye]));
+            ranked.remove(bye);
+        }
+        let mut pairings = Vec::new();
+        while !ranked.is_empty() {
+            let a = ranked.remove(0);
+            let b = ranked.iter().position(|b| !met(a, *b)).unwrap_or(0);
+            pairings.extend(self.match_pairings(a, ranked.remove(b)));
+        }
+        return pairings;
+    }
+
+    // Plays the tournament in its format and returns the records
+    // of its games in the order of the schedule, including those
+    // that were already completed
+    pub fn run(&self) -> Result<Vec<GameRecord>, Error> {
+        self.check_entrants()?;
+        let mut records = Vec::new();
+        match self.format {
+            Format::RoundRobin => for _i in 0..self.rounds {
+                self.play_all(&self.round_robin_pairings(), &mut records)?;
+            },
+            Format::Gauntlet => for _i in 0..self.rounds {
+                self.play_all(&self.gauntlet_pairings(), &mut records)?;
+            },
+            Format::Swiss => for round in 0..self.rounds {
+                println!("swiss round {}", round + 1);
+                let pairings = self.swiss_pairings(&records);
+                self.play_all(&pairings, &mut records)?;
+            },
+            Format::Knockout => self.knockout(&mut records)?,
+            Format::Sprt => {
+                self.play_sprt(&mut records)?;
+            },
+        }
+        self.write_summary(&records)?;
+        return Ok(records);
+    }
+
+    // Fails if an entrant can't play under the rules, or should
+    // ponder and can't, so that the tournament stops before its
+    // first game rather than during it. An entrant whose AI can't
+    // be created is left for its games to record
+    pub fn check_entrants(&self) -> Result<(), Error> {
+        let standard = self.rules == Rules::default();
+        for entrant in self.entrants.iter().filter(|e| e.ponder || !standard) {
+            if let Ok(mut ai) = (entrant.make_ai)() {
+                let can_play = ai.set_rules(self.rules);
+                let can_ponder = !entrant.ponder || ai.set_ponder(true);
+                ai.cleanup();
+                if !can_play {
+                    return Err(Error::Unsupported { ai: entrant.name.clone(),
+                                                    what: format!("the rules {}", self.rules) });
+                }
+                if !can_ponder {
+                    return Err(Error::Unsupported { ai: entrant.name.clone(),
+                                                    what: "ponder".to_string() });
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Plays the games of pairings in order, adding their records to
+    // records. Every game's seed follows that of the game before it
+    fn play_all(&self, pairings: &[Pairing], records: &mut Vec<GameRecord>) -> Result<(), Error> {
+        for pairing in pairings {
+            let seed = self.first_seed.wrapping_add(records.len() as u64);
+            let record = match self.find_completed(pairing, seed) {
+                Some(record) => record.clone(),
+                None => self.play(pairing, seed)?,
+            };
+            records.push(record);
+        }
+        return Ok(());
+    }
+
+    // Plays pairs of games between the first two entrants, going
+    // through the openings in turn, until the SPRT decides or rounds
+    // pairs have been played, and returns what it decided. Fails
+    // before the first game without two entrants, an opening or a
+    // valid sprt
+    pub fn play_sprt(&self, records: &mut Vec<GameRecord>) -> Result<Decision, Error> {
+        if self.entrants.len() < 2 {
+            return Err(Error::Invalid("an SPRT needs a candidate and a baseline".to_string()));
+        }
+        if self.openings.is_empty() {
+            return Err(Error::Invalid("an SPRT needs at least one opening".to_string()));
+        }
+        Sprt::new(self.sprt.elo0, self.sprt.elo1, self.sprt.alpha, self.sprt.beta)?;
+        let (lower, upper) = self.sprt.bounds();
+        println!("SPRT elo0 {} elo1 {} alpha {} beta {}, LLR bounds ({:.2}, {:.2})",
+                 self.sprt.elo0, self.sprt.elo1, self.sprt.alpha, self.sprt.beta, lower, upper);
+        let mut pairs = [0u32; 5];
+        let mut decision = Decision::Continue;
+        for i in 0..self.rounds {
+            let opening = i % self.openings.len();
+            let start = records.len();
+            self.play_all(&[Pairing { x: 0, o: 1, opening }, Pairing { x: 1, o: 0, opening }],
+                          records)?;
+            let points = self.scores(&records[start..])[0];
+            pairs[(points * 2.0) as usize] += 1;
+            decision = self.sprt.decide(&pairs);
+            let score = Sprt::score(&pairs);
+            println!("pair {}: {:?} score {:.3} elo {:.1} LLR {:.2} ({:.2}, {:.2})", i + 1, pairs,
+                     score, sprt::score_to_elo(score), self.sprt.llr(&pairs), lower, upper);
+            if decision != Decision::Continue {
+                break;
+            }
+        }
+        let candidate = &self.entrants[0].name;
+        match decision {
+            Decision::AcceptH1 => println!("H1 accepted: {} is stronger", candidate),
+            Decision::AcceptH0 => println!("H0 accepted: {} isn't stronger", candidate),
+            Decision::Continue => println!("no decision after {} pairs", self.rounds),
+        }
+        if let Some(path) = &self.json {
+            jsonl::append(path, &jsonl::sprt(&self.sprt, &pairs, decision))?;
+        }
+        return Ok(decision);
+    }
+
+    // Plays the matches of a knockout until one entrant is left
+    fn knockout(&self, records: &mut Vec<GameRecord>) -> Result<(), Error> {
+        let mut left: Vec<usize> = (0..self.entrants.len()).collect();
+        while left.len() > 1 {
+            let mut through = Vec::new();
+            if left.len() % 2 == 1 {
+                through.push(left.remove(0));
+            }
+            for i in 0..left.len() / 2 {
+                let (a, b) = (left[i], left[left.len() - 1 - i]);
+                let start = records.len();
+                for _j in 0..self.rounds {
+                    self.play_all(&self.match_pairings(a, b), records)?;
+                }
+                let mut tiebreaks = 0;
+                while self.scores(&records[start..])[a] == self.scores(&records[start..])[b]
+                      && tiebreaks < KNOCKOUT_TIEBREAKS {
+                    let pairings = [Pairing { x: a, o: b, opening: 0 },
+                                    Pairing { x: b, o: a, opening: 0 }];
+                    self.play_all(&pairings, records)?;
+                    tiebreaks += 1;
+                }
+                let scores = self.scores(&records[start..]);
+                let (winner, loser) = if scores[b] > scores[a] { (b, a) } else { (a, b) };
+                println!("{} knocks out {} {}-{}", self.entrants[winner].name,
+                         self.entrants[loser].name, scores[winner], scores[loser]);
+                through.push(winner);
+            }
+            through.sort_unstable();
+            left = through;
+        }
+        if let Some(winner) = left.first() {
+            println!("{} wins the knockout", self.entrants[*winner].name);
+        }
+        return Ok(());
+    }
+
+    // The completed game of one pairing with the given seed, if any
+    pub fn find_completed(&self, pairing: &Pairing, seed: u64) -> Option<&GameRecord> {
+        let (x, o) = (&self.entrants[pairing.x].name, &self.entrants[pairing.o].name);
+        return self.completed.iter().find(|r| r.seed == seed && r.x == *x && r.o == *o);
+    }
+
+    // Plays the game of one pairing with the given seed. AIs
+    // that fail lose the game rather than ending the tournament
+    pub fn play(&self, pairing: &Pairing, seed: u64) -> Result<GameRecord, Error> {
+        let x = &self.entrants[pairing.x];
+        let o = &self.entrants[pairing.o];
+        let record = match ((x.make_ai)(), (o.make_ai)()) {
+            (Ok(mut x_ai), Ok(mut o_ai)) => {
+                for (entrant, ai) in [(x, &mut x_ai), (o, &mut o_ai)].iter_mut() {
+                    // check_entrants has made sure it can
+                    if !ai.set_ponder(entrant.ponder) && entrant.ponder {
+                        println!("{} can't ponder, playing without", entrant.name);
+                    }
+                }
+                play_game(&x.name, &mut *x_ai, &o.name, &mut *o_ai,
+                          &self.openings[pairing.opening], seed, self.adjudication,
+                          self.rules)?
+            },
+            (x_ai, o_ai) => failed_start(GameRecord::new(&x.name, &o.name, seed), x_ai, o_ai),
+        };
+        println!("{}", record);
+        if let Some(path) = &self.records {
+            record.append(path)?;
+        }
+        if let Some(path) = &self.json {
+            jsonl::append(path, &jsonl::game(&record))?;
+        }
+        return Ok(record);
+    }
+
+    // Appends the summary of records to the JSON file, if any
+    pub fn write_summary(&self, records: &[GameRecord]) -> Result<(), Error> {
+        if let Some(path) = &self.json {
+            let summary = jsonl::summary(self.format, self.first_seed, records.len(),
+                                         &self.standings(records));
+            jsonl::append(path, &summary)?;
+        }
+        return Ok(());
+    }
+
+    // The results of every entrant in records, best score first,
+    // ties in the order the entrants are listed. Games that weren't
+    // played don't count
+    pub fn standings(&self, records: &[GameRecord]) -> Vec<Standing> {
+        let mut standings = Vec::new();
+        for e in &self.entrants {
+            let mut s = Standing { name: e.name.clone(), games: 0, wins: 0, draws: 0, losses: 0,
+                                   score: 0.0 };
+            for r in records.iter().filter(|r| r.x == e.name || r.o == e.name) {
+                let own = if r.x == e.name { Player::X } else { Player::O };
+                match r.winner {
+                    Player::NEITHER => continue,
+                    Player::DEAD => s.draws += 1,
+                    w if w == own => s.wins += 1,
+                    _ => s.losses += 1,
+                }
+                s.games += 1;
+            }
+            s.score = s.wins as f32 + s.draws as f32 / 2.0;
+            standings.push(s);
+        }
+        standings.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        return standings;
+    }
+
+    // The points each entrant scored in records, one for a win
+    // and half for a draw, in the order of the entrants
+    pub fn scores(&self, records: &[GameRecord]) -> Vec<f32> {
+        let mut scores = vec![0.0; self.entrants.len()];
+        let index = |name: &str| self.entrants.iter().position(|e| e.name == name);
+        for r in records {
+            let (x, o) = (index(&r.x), index(&r.o));
+            let (x_points, o_points) = match r.winner {
+                Player::X => (1.0, 0.0),
+                Player::O => (0.0, 1.0),
+                Player::DEAD => (0.5, 0.5),
+                Player::NEITHER => continue,
+            };
+            if let Some(i) = x {
+                scores[i] += x_points;
+            }
+            if let Some(i) = o {
+                scores[i] += o_points;
+            }
+        }
+        return scores;
+    }
+}
+
+// standings as a table with a line per entrant, for people
+pub fn standings_table(standings: &[Standing]) -> String {
+    let width = standings.iter().map(|s| s.name.len()).max().unwrap_or(0).max(4);
+    let mut table = format!("{:>4}  {:<width$}  {:>5}  {:>4}  {:>4}  {:>4}  {:>6}  {:>5}\n",
+                            "rank", "name", "games", "wins", "draw", "loss", "score", "%",
+                            width = width);
+    for (i, s) in standings.iter().enumerate() {
+        let percent = if s.games == 0 { 0.0 } else { 100.0 * s.score / s.games as f32 };
+        table += &format!("{:>4}  {:<width$}  {:>5}  {:>4}  {:>4}  {:>4}  {:>6.1}  {:>5.1}\n",
+                          i + 1, s.name, s.games, s.wins, s.draws, s.losses, s.score, percent,
+                          width = width);
+    }
+    return table;
+}
+
+// The record of a game that couldn't be played because x_ai
+// or o_ai failed to start. An AI that did start wins
+fn failed_start(mut record: GameRecord, x_ai: Result<Box<dyn AI>, Error>,
+                o_ai: Result<Box<dyn AI>, Error>) -> GameRecord {
+    let mut started = Vec::new();
+    for (player, ai) in [(Player::X, x_ai), (Player::O, o_ai)] {
+        match ai {
+            Ok(mut ai) => {
+                ai.cleanup();
+                started.push(player);
+            },
+            Err(why) => println!("{:?} failed to start: {}", player, why),
+        }
+    }
+    record.winner = match started.as_slice() {
+        [player] => *player,
+        _ => Player::NEITHER,
+    };
+    record.termination = Termination::EngineError;
+    return record;
+}
+
+// Plays a game under rules between x_ai and o_ai from the
+// position after opening, after seeding them from seed, and
+// returns its record. The game may be ended early by the
+// adjudication rules. An AI that fails during the game, or can't
+// play under the rules or start from the opening, loses it, and
+// a game whose opening is illegal isn't played
+#[allow(clippy::too_many_arguments)]
+pub fn play_game(x_name: &str, x_ai: &mut dyn AI, o_name: &str, o_ai: &mut dyn AI,
+                 opening: &[usize], seed: u64, adjudication: Adjudication,
+                 rules: Rules) -> Result<GameRecord, Error> {
+    let mut record = GameRecord::new(x_name, o_name, seed);
+    let (x_seed, o_seed) = GameRecord::ai_seeds(seed);
+    x_ai.set_seed(x_seed);
+    o_ai.set_seed(o_seed);
+    let mut board = Board::new(2, rules)?;
+    // Neither AI is to blame for a bad opening, so the game
+    // isn't scored
+    for m in opening {
+        if let Err(why) = board.make_move(*m) {
+            println!("bad opening: {}", why);
+            record.termination = Termination::IllegalMove;
+            x_ai.cleanup();
+            o_ai.cleanup();
+            return Ok(record);
+        }
+    }
+    let start = |name: &str, ai: &mut dyn AI| {
+        if !ai.set_rules(rules) {
+            println!("{} can't play under the rules {}", name, rules);
+            return false;
+        }
+        if !opening.is_empty() && !ai.set_opening(opening) {
+            println!("{} can't start from an opening", name);
+            return false;
+        }
+        return true;
+    };
+    let (x_ok, o_ok) = (start(x_name, &mut *x_ai), start(o_name, &mut *o_ai));
+    if !x_ok || !o_ok {
+        record.winner = match (x_ok, o_ok) {
+            (true, false) => Player::X,
+            (false, true) => Player::O,
+            _ => Player::NEITHER,
+        };
+        record.termination = Termination::EngineError;
+        x_ai.cleanup();
+        o_ai.cleanup();
+        return Ok(record);
+    }
+    if !opening.is_empty() {
+        record.opening = opening.len();
+        board.pretty_print();
+        println!();
+    }
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut last_move = -1;
+    while board.winner == Player::NEITHER {
+        let (to_move, opponent) = match board.get_to_move() {
+            Player::X => (Player::X, Player::O),
+            _ => (Player::O, Player::X),
+        };
+        let ai: &mut dyn AI = if to_move == Player::X { &mut *x_ai } else { &mut *o_ai };
+        let now = Instant::now();
+        let result = ai.get_move(last_move);
+        record.times.push(now.elapsed().as_millis());
+        record.scores.push(if result.is_ok() { ai.last_score() } else { None });
+        last_move = match result {
+            Ok(m) => m,
+            Err(why) => {
+                println!("{:?} failed: {}", to_move, why);
+                board.winner = opponent;
+                record.termination = Termination::EngineError;
+                break;
+            },
+        };
+        if last_move == -1 {
+            println!("{:?} forfeited", to_move);
+            board.winner = opponent;
+            record.termination = Termination::Forfeit;
+            break;
+        }
+        if let Err(reason) = board.check_move(last_move as usize) {
+            println!("{:?} forfeits with illegal move {}: {}", to_move, last_move, reason);
+            board.winner = opponent;
+            record.termination = Termination::IllegalMove;
+            break;
+        }
+        board.make_move(last_move as usize)?;
+        board.pretty_print();
+        println!();
+        let score = record.scores[record.scores.len() - 1];
+        if let Some((winner, termination)) = adjudicator.after_move(&board, to_move, score) {
+            println!("adjudicated: {}", termination);
+            board.winner = winner;
+            record.termination = termination;
+            break;
+        }
+    }
+    x_ai.cleanup();
+    o_ai.cleanup();
+    println!("{:?} wins", board.winner);
+    record.moves = board.move_history.iter().map(|t| t.space).collect();
+    record.winner = board.winner;
+    return Ok(record);
+}
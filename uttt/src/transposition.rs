@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// How a stored score relates to the true score of a position
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Bound {
+    // The score is exact
+    Exact,
+    // The true score is at least the stored score
+    Lower,
+    // The true score is at most the stored score
+    Upper,
+}
+
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct TableEntry {
+    pub score: i32,
+    // The best move found from this position, or -1 if none
+    pub best_move: i64,
+    // The depth of the search that produced this entry
+    pub depth: usize,
+    pub bound: Bound,
+}
+
+// A fixed-size hash table of search results that can be shared
+// between search threads without locking
+//
+// Each slot stores the entry's data and the position's hash xored
+// with that data. A slot torn by two threads writing at once fails
+// the key check on probe, so a reader either sees a whole entry
+// or nothing
+pub struct TranspositionTable {
+    keys: Vec<AtomicU64>,
+    data: Vec<AtomicU64>,
+}
+
+impl TranspositionTable {
+    // Creates a table with room for 2^bits entries
+    pub fn new(bits: u32) -> TranspositionTable {
+        let size = 1 << bits;
+        TranspositionTable {
+            keys: (0..size).map(|_| AtomicU64::new(0)).collect(),
+            data: (0..size).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        return (hash as usize) & (self.keys.len() - 1);
+    }
+
+    // Returns the entry stored for the position with the given
+    // hash, if there is one
+    pub fn probe(&self, hash: u64) -> Option<TableEntry> {
+        let i = self.index(hash);
+        let data = self.data[i].load(Ordering::Relaxed);
+        let key = self.keys[i].load(Ordering::Relaxed);
+        if data == 0 || key ^ data != hash {
+            return None;
+        }
+        return Some(TranspositionTable::unpack(data));
+    }
+
+    // Stores entry for the position with the given hash, replacing
+    // whatever was in its slot unless that came from a deeper search
+    // of the same position
+    pub fn store(&self, hash: u64, entry: TableEntry) {
+        if let Some(old) = self.probe(hash) {
+            if old.depth > entry.depth {
+                return;
+            }
+        }
+        let i = self.index(hash);
+        let data = TranspositionTable::pack(entry);
+        self.keys[i].store(hash ^ data, Ordering::Relaxed);
+        self.data[i].store(data, Ordering::Relaxed);
+    }
+
+    // Removes every entry from the table
+    pub fn clear(&self) {
+        for i in 0..self.keys.len() {
+            self.keys[i].store(0, Ordering::Relaxed);
+            self.data[i].store(0, Ordering::Relaxed);
+        }
+    }
+
+    // Bits 0-31 hold the score, 32-39 the best move plus one,
+    // 40-55 the depth and 56-57 the bound. Bit 58 is always set
+    // so that no stored entry packs to zero
+    fn pack(entry: TableEntry) -> u64 {
+        let bound = match entry.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        return (entry.score as u32 as u64)
+               | (((entry.best_move + 1) as u64 & 0xff) << 32)
+               | ((entry.depth as u64 & 0xffff) << 40)
+               | (bound << 56)
+               | (1 << 58);
+    }
+
+    fn unpack(data: u64) -> TableEntry {
+        TableEntry {
+            score: data as u32 as i32,
+            best_move: ((data >> 32) & 0xff) as i64 - 1,
+            depth: ((data >> 40) & 0xffff) as usize,
+            bound: match (data >> 56) & 0x3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_store_and_probe() {
+         let table = TranspositionTable::new(4);
+         let entry = TableEntry { score: -1234, best_move: 80, depth: 7, bound: Bound::Lower };
+         assert_eq!(table.probe(99), None);
+         table.store(99, entry);
+         assert_eq!(table.probe(99), Some(entry));
+         // Same slot, different position
+         assert_eq!(table.probe(99 + 16), None);
+         let shallow = TableEntry { score: 5, best_move: -1, depth: 2, bound: Bound::Exact };
+         table.store(99, shallow);
+         assert_eq!(table.probe(99), Some(entry));
+         table.clear();
+         assert_eq!(table.probe(99), None);
+     }
+}
@@ -0,0 +1,239 @@
+// Fits LinearEvaluator weights to the results of self-play games
+// (Texel's tuning method)
+//
+// Every position of every game is labelled with the game's result
+// from X's perspective (1 for an X win, 0.5 for a draw and 0 for
+// an O win). A weight set predicts that result as
+// sigmoid(score) = 1 / (1 + 10^(-score / scale)), and tuning
+// searches for the weights minimising the mean squared error
+// between predictions and results
+use crate::bitboard::BitBoard;
+use crate::error::Error;
+use crate::eval::{LinearEvaluator, Weights, NUM_FEATURES};
+use crate::simplesearchcenter::SimpleSearchCenterAI;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::fs;
+
+// A finished self-play game
+pub struct TuningGame {
+    pub moves: Vec<usize>,
+    // 1 if X won, -1 if O won, -2 if the game was drawn
+    pub winner: i8,
+}
+
+impl TuningGame {
+    fn result(&self) -> f64 {
+        match self.winner {
+            1 => 1.0,
+            -1 => 0.0,
+            _ => 0.5,
+        }
+    }
+}
+
+// Plays games between two copies of a depth limited
+// SimpleSearchCenterAI using weights. The first random_plies
+// moves of each game are random so that the games differ. The
+// same seed always produces the same games
+pub fn self_play(weights: Weights, games: usize, depth: usize,
+                 random_plies: usize, seed: u64) -> Vec<TuningGame> {
+    let searcher = SimpleSearchCenterAI::new(Box::new(LinearEvaluator::new(weights)),
+                                             depth.max(1), 1, 0);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = Vec::with_capacity(games);
+    for _i in 0..games {
+        let mut board = BitBoard::new();
+        let mut moves = Vec::new();
+        while board.get_winner() == 0 {
+            let m = if moves.len() < random_plies {
+                BitBoard::random_move(board.get_moves(), &mut rng).trailing_zeros() as i64
+            } else {
+                searcher.search(&mut board.clone(), depth.max(1), -100000000, 100000000,
+                                &mut rng).0
+            };
+            board.make_move(1 << m);
+            moves.push(m as usize);
+        }
+        result.push(TuningGame { moves, winner: board.get_winner() });
+    }
+    return result;
+}
+
+// Writes games one per line as the winner (X, O or D)
+// followed by the moves
+pub fn save_games(games: &[TuningGame], path: &str) -> Result<(), Error> {
+    let mut text = String::new();
+    for g in games {
+        text += match g.winner {
+            1 => "X",
+            -1 => "O",
+            _ => "D",
+        };
+        for m in &g.moves {
+            text += &format!(" {}", m);
+        }
+        text += "\n";
+    }
+    return fs::write(path, text).map_err(|e| Error::Io(format!("couldn't write {}: {}", path, e)));
+}
+
+pub fn load_games(path: &str) -> Result<Vec<TuningGame>, Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("couldn't read {}: {}", path, e)))?;
+    let mut games = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let winner = match words.next() {
+            None => continue,
+            Some("X") => 1,
+            Some("O") => -1,
+            Some("D") => -2,
+            Some(w) => return Err(Error::Parse(format!("line {}: unknown result {}", n + 1, w))),
+        };
+        let mut moves = Vec::new();
+        for w in words {
+            match w.parse::<usize>() {
+                Ok(m) if m < 81 => moves.push(m),
+                _ => return Err(Error::Parse(format!("line {}: bad move {}", n + 1, w))),
+            }
+        }
+        games.push(TuningGame { moves, winner });
+    }
+    return Ok(games);
+}
+
+// A position's features and the result of its game
+pub struct Sample {
+    features: [i32; NUM_FEATURES],
+    result: f64,
+}
+
+// Extracts a sample from every position of games in which
+// the game is not over yet
+pub fn samples(games: &[TuningGame]) -> Result<Vec<Sample>, Error> {
+    let mut samples = Vec::new();
+    for g in games {
+        let mut board = BitBoard::new();
+        for m in &g.moves {
+            board.check_move(*m).map_err(|reason| Error::illegal_move(*m, reason))?;
+            samples.push(Sample {
+                features: LinearEvaluator::features(&board),
+                result: g.result(),
+            });
+            board.make_move(1 << m);
+        }
+    }
+    return Ok(samples);
+}
+
+// Mean squared error of the predictions of features weighted
+// by weights against the samples' results
+pub fn error(samples: &[Sample], weights: &[i32; NUM_FEATURES], scale: f64) -> f64 {
+    let mut total = 0.0;
+    for s in samples {
+        let mut score = 0;
+        for (f, w) in s.features.iter().zip(weights.iter()) {
+            score += f * w;
+        }
+        let prediction = 1.0 / (1.0 + 10f64.powf(-score as f64 / scale));
+        total += (s.result - prediction) * (s.result - prediction);
+    }
+    return total / samples.len() as f64;
+}
+
+// Finds the scale that best fits the samples with the
+// given weights, so that tuning only changes the relative
+// size of the weights
+pub fn fit_scale(samples: &[Sample], weights: &[i32; NUM_FEATURES]) -> f64 {
+    let mut best = 1.0;
+    let mut best_error = error(samples, weights, best);
+    let mut scale = 1.0;
+    while scale < 100000.0 {
+        scale *= 1.1;
+        let e = error(samples, weights, scale);
+        if e < best_error {
+            best = scale;
+            best_error = e;
+        }
+    }
+    return best;
+}
+
+// Local search over the feature weights: try moving each weight
+// up and down by step, keeping any change that lowers the error,
+// and halve step once no change helps. Prints progress
+pub fn tune(samples: &[Sample], start: Weights, initial_step: i32) -> Weights {
+    let mut weights = start;
+    let scale = fit_scale(samples, &weights.features);
+    let mut best_error = error(samples, &weights.features, scale);
+    println!("scale: {:.1} initial error: {:.6}", scale, best_error);
+    let mut step = initial_step;
+    while step > 0 {
+        let mut improved = false;
+        for i in 0..NUM_FEATURES {
+            for delta in [step, -step].iter() {
+                let mut candidate = weights.features;
+                candidate[i] += delta;
+                let e = error(samples, &candidate, scale);
+                if e < best_error {
+                    best_error = e;
+                    weights.features = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        println!("step: {} error: {:.6}", step, best_error);
+        if !improved {
+            step /= 2;
+        }
+    }
+    return weights;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+     #[test]
+     fn test_tuning_lowers_error() {
+         let games = self_play(Weights::diagonal2(), 10, 1, 6, 0);
+         let samples = samples(&games).unwrap();
+         // Weights of the wrong sign, which favour the player
+         // that goes on to lose
+         let mut start = Weights::diagonal2();
+         for w in start.features.iter_mut() {
+             *w = -*w;
+         }
+         let scale = fit_scale(&samples, &start.features);
+         let tuned = tune(&samples, start, 256);
+         let before = error(&samples, &start.features, scale);
+         let after = error(&samples, &tuned.features, scale);
+         assert!(after < before * 0.9, "error {} before tuning, {} after", before, after);
+     }
+
+     #[test]
+     fn test_save_and_load_games() {
+         let games = self_play(Weights::abriand_eval_1(), 2, 1, 4, 0);
+         let path = std::env::temp_dir().join("uttt_test_tuning_games.txt");
+         let path = path.to_str().unwrap();
+         save_games(&games, path).unwrap();
+         let loaded = load_games(path).unwrap();
+         assert_eq!(loaded.len(), 2);
+         for (a, b) in games.iter().zip(loaded.iter()) {
+             assert_eq!(a.moves, b.moves);
+             assert_eq!(a.winner, b.winner);
+         }
+         assert!(!samples(&loaded).unwrap().is_empty());
+     }
+
+     #[test]
+     fn test_self_play_is_seeded() {
+         let a = self_play(Weights::diagonal2(), 3, 1, 4, 7);
+         let b = self_play(Weights::diagonal2(), 3, 1, 4, 7);
+         for (g, h) in a.iter().zip(b.iter()) {
+             assert_eq!(g.moves, h.moves);
+         }
+     }
+}
@@ -0,0 +1,79 @@
+#![allow(clippy::needless_return)]
+
+// Properties of make_move and undo_move that must hold for every
+// legal game under every rule variant, checked on random games
+
+use proptest::prelude::*;
+use uttt::rules::{DrawnSquares, FreeMove, TieBreak};
+use uttt::{Board, Player, Rules};
+
+fn rules() -> impl Strategy<Value = Rules> {
+    return (any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>())
+        .prop_map(|(most_squares, both, any_board, dead_boards)| Rules {
+            tie_break: if most_squares { TieBreak::MostSquares } else { TieBreak::Draw },
+            drawn_squares: if both { DrawnSquares::Both } else { DrawnSquares::Nobody },
+            free_move: if any_board { FreeMove::AnyBoard } else { FreeMove::OpenBoards },
+            dead_boards,
+        });
+}
+
+// Plays the legal move picked by each choice until the choices
+// run out or the game ends, returning the moves played
+fn play(board: &mut Board, choices: &[usize]) -> Vec<usize> {
+    let mut moves = Vec::new();
+    for c in choices {
+        let legal = board.get_moves();
+        if legal.is_empty() {
+            break;
+        }
+        let m = legal[c % legal.len()];
+        assert!(board.make_move(m).is_ok());
+        moves.push(m);
+    }
+    return moves;
+}
+
+proptest! {
+    #[test]
+    fn undo_restores_board(rules in rules(),
+                           before in prop::collection::vec(any::<usize>(), 0..40),
+                           after in prop::collection::vec(any::<usize>(), 0..81)) {
+        let mut board = Board::new(2, rules).unwrap();
+        play(&mut board, &before);
+        let original = board.clone();
+        let moves = play(&mut board, &after);
+        for _m in &moves {
+            prop_assert!(board.undo_move());
+        }
+        prop_assert_eq!(board, original);
+    }
+
+    #[test]
+    fn moves_exist_until_game_over(rules in rules(),
+                                   choices in prop::collection::vec(any::<usize>(), 81)) {
+        let mut board = Board::new(2, rules).unwrap();
+        for c in &choices {
+            let legal = board.get_moves();
+            prop_assert_eq!(legal.is_empty(), board.winner != Player::NEITHER);
+            if legal.is_empty() {
+                break;
+            }
+            prop_assert!(board.make_move(legal[c % legal.len()]).is_ok());
+            prop_assert_eq!(board.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn illegal_moves_change_nothing(rules in rules(),
+                                    choices in prop::collection::vec(any::<usize>(), 0..81),
+                                    space in 0usize..81) {
+        let mut board = Board::new(2, rules).unwrap();
+        play(&mut board, &choices);
+        if !board.get_moves().contains(&space) {
+            let before = board.clone();
+            prop_assert!(board.check_move(space).is_err());
+            prop_assert!(board.make_move(space).is_err());
+            prop_assert_eq!(board, before);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+#![allow(clippy::needless_return)]
+
+// Uses the library only through its public API, the way a
+// program depending on the crate would
+
+use uttt::adjudication::Adjudication;
+use uttt::eval::Weights;
+use uttt::notation::{game_name, parse_game};
+use uttt::simplesearchcenter::SimpleSearchCenterAI;
+use uttt::tournament::{play_game, standings_table, Format, Pairing};
+use uttt::record::Termination;
+use uttt::sprt::{Decision, Sprt};
+use std::cell::Cell;
+use std::rc::Rc;
+use uttt::{Board, Entrant, Error, GameRecord, PipeAI, Player, Rules, Tournament, AI};
+
+// Plays the lowest legal space
+struct FirstMoveAI {
+    board: Board,
+}
+
+impl FirstMoveAI {
+    fn new() -> FirstMoveAI {
+        FirstMoveAI { board: Board::new(2, Rules::default()).unwrap() }
+    }
+}
+
+impl AI for FirstMoveAI {
+    fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+        if last_move != -1 {
+            self.board.make_move(last_move as usize)?;
+        }
+        let m = self.board.get_moves()[0];
+        self.board.make_move(m)?;
+        return Ok(m as i64);
+    }
+
+    fn cleanup(&mut self) {}
+
+    fn set_opening(&mut self, moves: &[usize]) -> bool {
+        return moves.iter().all(|m| self.board.make_move(*m).is_ok());
+    }
+}
+
+// Plays like FirstMoveAI but can only start from the empty board
+struct NoOpeningAI {
+    ai: FirstMoveAI,
+}
+
+impl AI for NoOpeningAI {
+    fn get_move(&mut self, last_move: i64) -> Result<i64, Error> {
+        return self.ai.get_move(last_move);
+    }
+
+    fn cleanup(&mut self) {}
+}
+
+#[test]
+fn test_board_and_notation() {
+    let moves = parse_game("e5 d4 a1", Rules::default()).unwrap();
+    assert_eq!(moves, vec![40, 36, 0]);
+    let mut board = Board::new(2, Rules::default()).unwrap();
+    for m in &moves {
+        assert!(board.make_move(*m).is_ok());
+    }
+    assert_eq!(board.validate(), Ok(()));
+    assert_eq!(board.get_to_move(), Player::O);
+    assert_eq!(game_name(&moves), "e5 d4 a1");
+}
+
+#[test]
+fn test_round_robin() {
+    let entrants = vec![
+        Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+        Entrant::new("search", Box::new(|| Ok(Box::new(
+            SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(), 1, 1, 0))))),
+    ];
+    let mut t = Tournament::new(entrants, 7);
+    t.openings = vec![vec![], vec![0]];
+    let json = std::env::temp_dir().join("uttt_test_round_robin.jsonl");
+    let _ = std::fs::remove_file(&json);
+    t.json = Some(json.to_str().unwrap().to_string());
+    let records = t.run().unwrap();
+    assert_eq!(records.len(), 4);
+    // A line for every game and the summary
+    let lines = std::fs::read_to_string(&json).unwrap();
+    let lines: Vec<&str> = lines.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with(
+        "{\"type\":\"game\",\"x\":\"first\",\"o\":\"search\",\"seed\":7,"));
+    assert!(lines[4].starts_with("{\"type\":\"summary\",\"format\":\"round-robin\",\"games\":4,"));
+    assert_eq!(records.iter().map(|r| r.seed).collect::<Vec<u64>>(), vec![7, 8, 9, 10]);
+    let scores = t.scores(&records);
+    assert_eq!(scores[0] + scores[1], 4.0);
+    for r in &records {
+        // Every game replays to the recorded result
+        let replayed = parse_game(&game_name(&r.moves), Rules::default()).unwrap();
+        assert_eq!(replayed, r.moves);
+        assert_eq!(r.to_string().parse::<GameRecord>(), Ok(r.clone()));
+        assert_eq!(r.scores.len(), r.times.len());
+    }
+}
+
+#[test]
+fn test_resume() {
+    // Counts the games the second entrant is created for
+    let starts = Rc::new(Cell::new(0));
+    let tournament = |starts: Rc<Cell<usize>>| {
+        let entrants = vec![
+            Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+            Entrant::new("counted", Box::new(move || {
+                starts.set(starts.get() + 1);
+                return Ok(Box::new(FirstMoveAI::new()));
+            })),
+        ];
+        let mut t = Tournament::new(entrants, 3);
+        t.rounds = 2;
+        return t;
+    };
+    let full = tournament(starts.clone()).run().unwrap();
+    assert_eq!(starts.get(), 4);
+    // Only the game that wasn't completed is played again
+    let mut t = tournament(starts.clone());
+    t.completed = full[..3].to_vec();
+    let resumed = t.run().unwrap();
+    assert_eq!(starts.get(), 5);
+    assert_eq!(resumed[..3], full[..3]);
+    assert_eq!((&resumed[3].x, resumed[3].seed, &resumed[3].moves),
+               (&full[3].x, full[3].seed, &full[3].moves));
+}
+
+// Entrants that are never created, for checking pairings
+fn unplayed(names: &[&str]) -> Vec<Entrant> {
+    return names.iter().map(|n| Entrant::new(n, Box::new(|| panic!("not played")))).collect();
+}
+
+#[test]
+fn test_pairings() {
+    let t = Tournament::new(unplayed(&["a", "b", "c"]), 0);
+    let gauntlet = t.gauntlet_pairings();
+    assert_eq!(gauntlet.len(), 4);
+    assert!(gauntlet.iter().all(|p| p.x == 0 || p.o == 0));
+    let t = Tournament::new(unplayed(&["a", "b", "c", "d", "e"]), 0);
+    let matches = |pairings: &[Pairing]| {
+        return pairings.iter().filter(|p| p.x < p.o).map(|p| (p.x, p.o))
+            .collect::<Vec<(usize, usize)>>();
+    };
+    assert_eq!(matches(&t.swiss_pairings(&[])), vec![(0, 1), (2, 3)]);
+    // a and c won their matches, and e sat out, so d sits out now
+    let mut records = Vec::new();
+    for (x, o, winner) in [("a", "b", Player::X), ("b", "a", Player::O),
+                           ("c", "d", Player::X), ("d", "c", Player::O)] {
+        let mut r = GameRecord::new(x, o, 0);
+        r.winner = winner;
+        records.push(r);
+    }
+    assert_eq!(matches(&t.swiss_pairings(&records)), vec![(0, 2), (1, 4)]);
+}
+
+#[test]
+fn test_formats() {
+    let entrants = || vec![
+        Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+        Entrant::new("first2", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+        Entrant::new("search", Box::new(|| Ok(Box::new(
+            SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(), 1, 1, 0))))),
+    ];
+    let mut t = Tournament::new(entrants(), 0);
+    t.format = Format::Swiss;
+    t.rounds = 2;
+    let records = t.run().unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records.iter().map(|r| r.seed).collect::<Vec<u64>>(), vec![0, 1, 2, 3]);
+    let standings = t.standings(&records);
+    assert_eq!(standings.iter().map(|s| s.games).sum::<usize>(), 8);
+    assert!(standings_table(&standings).lines().nth(1).unwrap().starts_with("   1  "));
+    // The searching AI beats the others, playing first2 and then
+    // first, which sits out the first round
+    let mut t = Tournament::new(entrants(), 0);
+    t.format = Format::Knockout;
+    let records = t.run().unwrap();
+    assert_eq!((records[0].x.as_str(), records[0].o.as_str()), ("first2", "search"));
+    assert_eq!(records[records.len() - 1].o, "first");
+    assert_eq!(t.standings(&records)[0].name, "search");
+    assert_eq!(t.standings(&records)[0].losses, 0);
+}
+
+#[test]
+fn test_sprt() {
+    // The searching AI wins every pair against the one playing
+    // the lowest space, which is enough to accept H1 after a few
+    let entrants = vec![
+        Entrant::new("search", Box::new(|| Ok(Box::new(
+            SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(), 1, 1, 0))))),
+        Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+    ];
+    let mut t = Tournament::new(entrants, 0);
+    t.format = Format::Sprt;
+    t.rounds = 20;
+    t.sprt = Sprt::new(0.0, 50.0, 0.05, 0.05).unwrap();
+    t.openings = vec![vec![], vec![40]];
+    let mut records = Vec::new();
+    assert_eq!(t.play_sprt(&mut records).unwrap(), Decision::AcceptH1);
+    assert!(records.len() > 2 && records.len() < 40);
+    for pair in records.chunks(2) {
+        // Colors swapped, same opening
+        assert_eq!((&pair[0].x, &pair[0].o), (&pair[1].o, &pair[1].x));
+        assert_eq!(pair[0].moves[..pair[0].opening], pair[1].moves[..pair[1].opening]);
+        assert_eq!(t.scores(pair)[0], 2.0);
+    }
+    // Without openings or a baseline there is nothing to play
+    t.openings.clear();
+    assert!(matches!(t.play_sprt(&mut records), Err(Error::Invalid(_))));
+    t.openings = vec![vec![]];
+    t.entrants.truncate(1);
+    assert!(matches!(t.run(), Err(Error::Invalid(_))));
+}
+
+#[test]
+fn test_failing_entrants() {
+    // An engine that can't be started loses its games,
+    // and the tournament goes on
+    let entrants = vec![
+        Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+        Entrant::new("missing", Box::new(|| {
+            let engine = PipeAI::new("/nonexistent/uttt-engine".to_string(), vec![])?;
+            return Ok(Box::new(engine));
+        })),
+    ];
+    let t = Tournament::new(entrants, 0);
+    let records = t.run().unwrap();
+    assert_eq!(records.len(), 2);
+    for r in &records {
+        assert_eq!(r.termination, Termination::EngineError);
+        assert!(r.moves.is_empty());
+    }
+    assert_eq!(t.scores(&records), vec![2.0, 0.0]);
+}
+
+#[test]
+fn test_ponder_unsupported() {
+    // Asking an AI that can't ponder to do so stops the tournament
+    // before its first game
+    let created = Rc::new(Cell::new(0));
+    let counter = created.clone();
+    let mut entrants = vec![
+        Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))),
+        Entrant::new("second", Box::new(move || {
+            counter.set(counter.get() + 1);
+            return Ok(Box::new(FirstMoveAI::new()));
+        })),
+    ];
+    entrants[1].ponder = true;
+    let mut t = Tournament::new(entrants, 0);
+    for format in &[Format::RoundRobin, Format::Swiss, Format::Sprt] {
+        t.format = *format;
+        match t.run() {
+            Err(Error::Unsupported { ai, what }) => assert_eq!((ai, what), ("second".to_string(),
+                                                                          "ponder".to_string())),
+            other => panic!("expected Unsupported, got {:?}", other.map(|r| r.len())),
+        }
+    }
+    assert_eq!(created.get(), 3);
+}
+
+#[test]
+fn test_opening_failures() {
+    // An AI that can't start from the opening loses without
+    // ending the tournament, and a bad opening isn't scored
+    let mut first = FirstMoveAI::new();
+    let mut stuck = NoOpeningAI { ai: FirstMoveAI::new() };
+    let record = play_game("first", &mut first, "stuck", &mut stuck, &[40],
+                           0, Adjudication::default(), Rules::default()).unwrap();
+    assert_eq!((record.winner, record.termination), (Player::X, Termination::EngineError));
+    assert!(record.moves.is_empty());
+    let mut first = FirstMoveAI::new();
+    let mut other = FirstMoveAI::new();
+    let record = play_game("first", &mut first, "other", &mut other, &[40, 40],
+                           0, Adjudication::default(), Rules::default()).unwrap();
+    assert_eq!((record.winner, record.termination), (Player::NEITHER, Termination::IllegalMove));
+}
+
+#[test]
+fn test_rules() {
+    // Games are played under the tournament's rules, which an AI
+    // that only knows the standard game can't play
+    let rules: Rules = "most-squares,drawn-for-both,any-board,dead-boards".parse().unwrap();
+    let search = || -> Result<Box<dyn AI>, Error> {
+        return Ok(Box::new(SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(),
+                                                     1, 1, 0)));
+    };
+    let entrants = vec![Entrant::new("a", Box::new(search)), Entrant::new("b", Box::new(search))];
+    let mut t = Tournament::new(entrants, 3);
+    t.rules = rules;
+    let records = t.run().unwrap();
+    assert_eq!(records.len(), 2);
+    for record in &records {
+        let mut board = Board::new(2, rules).unwrap();
+        for m in &record.moves {
+            assert!(board.make_move(*m).is_ok());
+        }
+        assert_eq!(board.winner, record.winner);
+    }
+    t.entrants.push(Entrant::new("first", Box::new(|| Ok(Box::new(FirstMoveAI::new())))));
+    match t.run() {
+        Err(Error::Unsupported { ai, .. }) => assert_eq!(ai, "first"),
+        other => panic!("expected Unsupported, got {:?}", other.map(|r| r.len())),
+    }
+    let mut search = search().unwrap();
+    let mut first = FirstMoveAI::new();
+    let record = play_game("search", &mut *search, "first", &mut first, &[], 0,
+                           Adjudication::default(), rules).unwrap();
+    assert_eq!((record.winner, record.termination), (Player::X, Termination::EngineError));
+}
+
+#[test]
+fn test_engine_over_uttti() {
+    let mut engine = PipeAI::new(env!("CARGO_BIN_EXE_uttt-engine").to_string(),
+                                 vec!["--ai".to_string(), "search".to_string()]).unwrap();
+    assert_eq!(engine.name(), "uttt-search");
+    assert_eq!(engine.set_option("Depth", "1"), Ok(()));
+    // It ponders through go ponder, ponderhit and stop
+    assert!(engine.set_ponder(true));
+    let mut first = FirstMoveAI::new();
+    let record = play_game("first", &mut first, "engine", &mut engine, &[40],
+                           0, Adjudication::default(), Rules::default()).unwrap();
+    assert!(record.winner != Player::NEITHER);
+    assert_eq!(record.termination, Termination::Normal);
+    assert_eq!(record.moves[0], 40);
+    assert_eq!(record.opening, 1);
+    // The rules reach the engine as its Rules option
+    let rules: Rules = "any-board,dead-boards".parse().unwrap();
+    let mut engine = PipeAI::new(env!("CARGO_BIN_EXE_uttt-engine").to_string(),
+                                 vec!["--ai".to_string(), "mcts".to_string()]).unwrap();
+    assert_eq!(engine.set_option("Playouts", "100"), Ok(()));
+    let mut search = SimpleSearchCenterAI::new(SimpleSearchCenterAI::diagonal2(), 1, 1, 0);
+    let record = play_game("search", &mut search, "engine", &mut engine, &[], 0,
+                           Adjudication::default(), rules).unwrap();
+    assert_eq!(record.termination, Termination::Normal);
+    let mut board = Board::new(2, rules).unwrap();
+    for m in &record.moves {
+        assert!(board.make_move(*m).is_ok());
+    }
+    assert_eq!(board.winner, record.winner);
+}
+
+#[test]
+fn test_entrant_specs() {
+    let weights = std::env::temp_dir().join("uttt_test_entrant_specs.txt");
+    let weights = weights.to_str().unwrap();
+    Weights::diagonal2().save(weights).unwrap();
+    let specs = [format!("search:{}:depth=1,seed=2", weights), "mcts:Playouts=100".to_string(),
+                 format!("pipe:{} --ai mcts", env!("CARGO_BIN_EXE_uttt-engine"))];
+    let entrants: Vec<Entrant> = specs.iter().map(|s| Entrant::from_spec(s).unwrap()).collect();
+    assert_eq!(entrants[2].name, specs[2]);
+    // Only an engine that answered the handshake can ponder
+    let mut engine = (entrants[2].make_ai)().unwrap();
+    assert!(engine.set_ponder(true));
+    engine.cleanup();
+    let t = Tournament::new(entrants.into_iter().take(2).collect(), 5);
+    let records = t.run().unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.termination == Termination::Normal));
+    assert_eq!(records[0].x, specs[0]);
+    for bad in ["alphabeta", "mcts:tuned.txt", "search:depth=0,width=3", "mcts:playouts",
+                "mcts:rules=any-board", "pipe:"] {
+        assert!(matches!(Entrant::from_spec(bad), Err(Error::Parse(_))), "{}", bad);
+    }
+    assert!(matches!(Entrant::from_spec("search:missing.txt"), Err(Error::Io(_))));
+}